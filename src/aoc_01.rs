@@ -0,0 +1,97 @@
+use std::collections::HashSet;
+use std::io::BufRead;
+
+use itertools::Itertools;
+
+use crate::error::Error;
+use crate::numbered_lines;
+use crate::solution::Solution;
+
+const TARGET: i32 = 2020;
+
+pub fn parse_numbers<T: BufRead>(reader: T) -> Result<Vec<i32>, Error> {
+    numbered_lines(reader).map(|line| {
+        let (line_no, text) = line?;
+
+        text.trim().parse::<i32>()
+            .map_err(|e| Error::parse(1, &text, format!("invalid number: {}", e)).on_line(line_no))
+    }).collect()
+}
+
+// Only numbers seen before `value` can pair with it, so a lone 1010 doesn't
+// pair with itself.
+pub fn find_pair(numbers: &[i32]) -> Option<(i32, i32)> {
+    let mut seen: HashSet<i32> = HashSet::new();
+
+    for value in numbers {
+        if let Some(inverse_value) = TARGET.checked_sub(*value) {
+            if seen.contains(&inverse_value) {
+                return Some((inverse_value, *value));
+            }
+        }
+
+        seen.insert(*value);
+    }
+
+    None
+}
+
+pub fn find_triple(numbers: &[i32]) -> Option<(i32, i32, i32)> {
+    numbers.iter()
+        .combinations(3)
+        .find(|set| set.iter().map(|value| i64::from(**value)).sum::<i64>() == i64::from(TARGET))
+        .map(|set| (*set[0], *set[1], *set[2]))
+}
+
+pub struct Day01;
+
+impl Solution for Day01 {
+    fn part_a(&self, input: &mut dyn BufRead) -> Result<String, Error> {
+        let (a, b) = find_pair(&parse_numbers(input)?)
+            .ok_or_else(|| Error::NoAnswer("no pair sums to 2020".to_string()))?;
+
+        // Products are widened so that no pair of i32s can overflow them.
+        Ok((i64::from(a) * i64::from(b)).to_string())
+    }
+
+    fn part_b(&self, input: &mut dyn BufRead) -> Result<String, Error> {
+        let (a, b, c) = find_triple(&parse_numbers(input)?)
+            .ok_or_else(|| Error::NoAnswer("no triple sums to 2020".to_string()))?;
+
+        Ok((i128::from(a) * i128::from(b) * i128::from(c)).to_string())
+    }
+}
+
+#[test]
+fn test_find_pair_and_triple() {
+    let numbers = [1721, 979, 366, 299, 675, 1456];
+
+    assert_eq!(Some((1721, 299)), find_pair(&numbers));
+    assert_eq!(Some((979, 366, 675)), find_triple(&numbers));
+
+    assert_eq!(None, find_pair(&[1010, 7]));
+    assert_eq!(Some((1010, 1010)), find_pair(&[1010, 7, 1010]));
+    assert_eq!(None, find_pair(&[i32::MIN, 5]));
+    assert_eq!(None, find_triple(&[i32::MAX, i32::MAX, 2020]));
+}
+
+#[test]
+fn test_large_answers() {
+    let answer = |input: &str, part_b: bool| {
+        let mut input = std::io::Cursor::new(input);
+        if part_b { Day01.part_b(&mut input) } else { Day01.part_a(&mut input) }
+    };
+
+    assert_eq!("-9798000000", answer("100000\n-97980\n", false).unwrap());
+    assert_eq!("-79999920000000000000", answer("2000000000\n-1999998000\n20\n", true).unwrap());
+}
+
+#[test]
+fn test_parse_numbers() {
+    assert_eq!(vec![1721, 979], parse_numbers(std::io::Cursor::new("1721\n979\n")).unwrap());
+
+    match parse_numbers(std::io::Cursor::new("1721\nabc\n")) {
+        Err(Error::Parse { line, text, .. }) => assert_eq!((2, "abc".to_string()), (line, text)),
+        other => panic!("Expected parse error, got {:?}", other),
+    }
+}
@@ -0,0 +1,124 @@
+use std::io::BufRead;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::error::Error;
+use crate::numbered_lines;
+use crate::solution::Solution;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct PasswordPolicy {
+    pub letter: char,
+    pub first: usize,
+    pub second: usize,
+}
+
+impl PasswordPolicy {
+    // Old policy: `first` and `second` are the minimum and maximum number of
+    // times the letter may appear.
+    pub fn check_count(&self, input: &str) -> bool {
+        let count = input.chars().filter(|c| *c == self.letter).count();
+
+        (self.first..=self.second).contains(&count)
+    }
+
+    // New policy: exactly one of the (1-indexed) positions `first` and
+    // `second` must hold the letter.
+    pub fn check_positions(&self, input: &str) -> bool {
+        let letter_at = |position: usize| position.checked_sub(1)
+            .and_then(|idx| input.chars().nth(idx)) == Some(self.letter);
+
+        letter_at(self.first) ^ letter_at(self.second)
+    }
+}
+
+pub fn parse(line: &str) -> Result<(PasswordPolicy, String), Error> {
+    match regex_captures!(r"^(\d+)-(\d+) (.): (.*)$", line) {
+        None => Err(Error::parse(1, line, "expected `<n>-<n> <letter>: <password>`")),
+        Some(captures) => {
+            let number = |idx: usize| {
+                let m = captures.get(idx).unwrap();
+
+                m.as_str().parse::<usize>()
+                    .map_err(|e| Error::parse(m.start() + 1, line, format!("invalid number: {}", e)))
+            };
+
+            Ok((
+                PasswordPolicy {
+                    letter: captures[3].chars().next().unwrap(),
+                    first: number(1)?,
+                    second: number(2)?,
+                },
+                captures[4].into()
+            ))
+        }
+    }
+}
+
+fn count_valid(input: &mut dyn BufRead, check: fn(&PasswordPolicy, &str) -> bool) -> Result<usize, Error> {
+    let mut count = 0;
+
+    for line in numbered_lines(input) {
+        let (line_no, text) = line?;
+        let (policy, password) = parse(&text).map_err(|e| e.on_line(line_no))?;
+
+        if check(&policy, &password) {
+            count += 1;
+        }
+    }
+
+    Ok(count)
+}
+
+pub struct Day02;
+
+impl Solution for Day02 {
+    fn part_a(&self, input: &mut dyn BufRead) -> Result<String, Error> {
+        Ok(count_valid(input, PasswordPolicy::check_count)?.to_string())
+    }
+
+    fn part_b(&self, input: &mut dyn BufRead) -> Result<String, Error> {
+        Ok(count_valid(input, PasswordPolicy::check_positions)?.to_string())
+    }
+}
+
+#[test]
+fn test_check_count() {
+    assert!(PasswordPolicy { letter: 'a', first: 1, second: 3 }.check_count("abcde"));
+    assert!(PasswordPolicy { letter: 'c', first: 2, second: 9 }.check_count("ccccccccc"));
+    assert!(!PasswordPolicy { letter: 'b', first: 1, second: 3 }.check_count("cdefg"));
+}
+
+#[test]
+fn test_check_positions() {
+    assert!(PasswordPolicy { letter: 'a', first: 1, second: 3 }.check_positions("abcde"));
+    assert!(!PasswordPolicy { letter: 'b', first: 1, second: 3 }.check_positions("cdefg"));
+    assert!(!PasswordPolicy { letter: 'c', first: 2, second: 9 }.check_positions("ccccccccc"));
+}
+
+#[test]
+fn test_parser() {
+    let (policy, password) = parse("2-12 d: abcdef").unwrap();
+
+    assert_eq!(
+        PasswordPolicy {
+            letter: 'd',
+            first: 2,
+            second: 12,
+        },
+        policy
+    );
+
+    assert_eq!("abcdef", password);
+}
+
+#[test]
+fn test_parser_errors() {
+    assert!(parse("2-12 d abcdef").is_err());
+
+    match parse("2-99999999999999999999999 d: abc") {
+        Err(Error::Parse { column, .. }) => assert_eq!(3, column),
+        other => panic!("Expected parse error, got {:?}", other),
+    }
+}
@@ -4,6 +4,10 @@ use array2d::Array2D;
 use std::io::BufRead;
 use core::default::Default;
 
+use crate::error::Error;
+use crate::numbered_lines;
+use crate::solution::Solution;
+
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum MapLoc {
     Empty,
@@ -41,26 +45,44 @@ pub struct Map {
 
 impl Map {
     #[cfg(test)]
-    pub fn from_str(str: &str) -> Map {
-        let buf = std::io::Cursor::new(str);
+    pub fn from_text(text: &str) -> Result<Map, Error> {
+        let buf = std::io::Cursor::new(text);
 
         Map::parse(buf)
     }
 
-    pub fn parse<R: BufRead>(reader: R) -> Map {
+    pub fn parse<R: BufRead>(reader: R) -> Result<Map, Error> {
         let mut rows_arr: Vec<Vec<MapLoc>> = vec![];
 
-        for line in reader.lines().flatten() {
-            rows_arr.push(line.chars().map(|c|
-                MapLoc::from_char(c)
-                    .unwrap_or_else(|| panic!("Unexpected character: {}", c))).collect());
+        for line in numbered_lines(reader) {
+            let (line_no, text) = line?;
+
+            let row = text.chars().enumerate().map(|(idx, c)|
+                MapLoc::from_char(c).ok_or_else(||
+                    Error::parse(idx + 1, &text, format!("unexpected character: {:?}", c)).on_line(line_no)))
+                .collect::<Result<Vec<MapLoc>, Error>>()?;
+
+            if let Some(first_row) = rows_arr.first() {
+                if row.len() != first_row.len() {
+                    let reason = format!("expected {} columns, found {}", first_row.len(), row.len());
+
+                    return Err(Error::parse(row.len().min(first_row.len()) + 1, &text, reason).on_line(line_no));
+                }
+            }
+
+            rows_arr.push(row);
         }
 
-        Map {
+        let width = match rows_arr.first() {
+            Some(row) if !row.is_empty() => row.len(),
+            _ => return Err(Error::parse(1, "", "map is empty")),
+        };
+
+        Ok(Map {
             rows: Array2D::from_rows(&rows_arr),
-            width: rows_arr.get(0).unwrap().len(),
+            width,
             pos: Default::default(),
-        }
+        })
     }
 
     pub fn peek(&self) -> &MapLoc {
@@ -95,9 +117,36 @@ impl Map {
     }
 }
 
+pub struct Day03;
+
+impl Solution for Day03 {
+    fn part_a(&self, input: &mut dyn BufRead) -> Result<String, Error> {
+        let mut map = Map::parse(input)?;
+
+        Ok(map.journey(Slope { right: 3, down: 1 }).to_string())
+    }
+
+    fn part_b(&self, input: &mut dyn BufRead) -> Result<String, Error> {
+        let mut map = Map::parse(input)?;
+
+        let slopes = [
+            Slope { right: 1, down: 1 },
+            Slope { right: 3, down: 1 },
+            Slope { right: 5, down: 1 },
+            Slope { right: 7, down: 1 },
+            Slope { right: 1, down: 2 },
+        ];
+
+        Ok(slopes.iter()
+            .map(|slope| map.journey(*slope))
+            .product::<usize>()
+            .to_string())
+    }
+}
+
 #[test]
 fn test_parse_map() {
-    let map = Map::from_str("..#\n.#.\n##.");
+    let map = Map::from_text("..#\n.#.\n##.").unwrap();
 
     use MapLoc::*;
     assert_eq!(
@@ -124,7 +173,7 @@ fn test_peek_and_slide() {
     ];
 
     for (expected, slope) in tests.iter() {
-        let mut map: Map = Map::from_str("..##.......\n\
+        let mut map: Map = Map::from_text("..##.......\n\
                                       #...#...#..\n\
                                       .#....#..#.\n\
                                       ..#.#...#.#\n\
@@ -134,9 +183,19 @@ fn test_peek_and_slide() {
                                       .#........#\n\
                                       #.##...#...\n\
                                       #...##....#\n\
-                                      .#..#...#.#");
+                                      .#..#...#.#").unwrap();
 
         assert_eq!(*expected, map.journey(*slope));
     }
 }
 
+#[test]
+fn test_parse_map_errors() {
+    match Map::from_text("..#\n.x.\n##.") {
+        Err(Error::Parse { line, column, .. }) => assert_eq!((2, 2), (line, column)),
+        other => panic!("Expected parse error, got {:?}", other),
+    }
+
+    assert!(Map::from_text("..#\n.#\n##.").is_err());
+    assert!(Map::from_text("").is_err());
+}
@@ -1,156 +1,431 @@
-use std::io::{BufRead};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::iter::Peekable;
 use lazy_static::lazy_static;
 use regex::{Regex};
-use enum_from_str::ParseEnumVariantError;
-use enum_from_str_derive::FromStr;
 use crate::RecordReader;
+use crate::error::Error;
+use crate::solution::Solution;
 
+pub mod export;
+pub mod policy;
+
+use policy::{Policy, DEFAULT_POLICY};
+
+// A passport is only its fields as read; what they mean, and which are
+// required, is up to a `Policy`.
 #[derive(Default, Clone, PartialEq, Eq, Debug)]
 pub struct Passport {
-    byr: Option<Year>,
-    iyr: Option<Year>,
-    eyr: Option<Year>,
-    hgt: Height,
-    hcl: Option<HairColor>,
-    ecl: Option<EyeColor>,
-    pid: Option<PassportID>,
-    cid: Option<String>,
+    raw: RawFields,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct RawField {
+    // The whitespace between the previous field, or the start of the record,
+    // and this one.
+    leading: String,
+    // The whole token if it has no `:`.
+    key: String,
+    // None if the token has no `:`, and so isn't a field at all.
+    value: Option<String>,
+}
+
+// Every `key:value` pair of a record in the order read, duplicates included,
+// along with the whitespace around them, so that displaying it gives back
+// the record exactly.
+#[derive(Default, Clone, PartialEq, Eq, Debug)]
+pub struct RawFields {
+    fields: Vec<RawField>,
+    // Whitespace after the last field, including the blank line ending the
+    // record.
+    trailing: String,
+}
+
+impl RawFields {
+    fn push(&mut self, leading: &str, key: &str, value: Option<&str>) {
+        self.fields.push(RawField { leading: leading.to_string(), key: key.to_string(), value: value.map(str::to_string) });
+    }
+
+    // The number of `key:value` fields.
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // The `key:value` fields, leaving out malformed tokens.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item=(&str, &str)> {
+        self.fields.iter().filter_map(|field| field.value.as_deref().map(|value| (field.key.as_str(), value)))
+    }
+
+    // Tokens without a `:`, in order.
+    pub fn malformed(&self) -> impl Iterator<Item=&str> {
+        self.fields.iter().filter(|field| field.value.is_none()).map(|field| field.key.as_str())
+    }
+
+    // Each key once, in the order first read.
+    pub fn keys(&self) -> Vec<&str> {
+        let mut keys: Vec<&str> = vec![];
+
+        for (key, _) in self.iter() {
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+
+        keys
+    }
+
+    // The last value given for `key`, which is the one policies check.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.iter().rev().find(|(k, _)| *k == key).map(|(_, value)| value)
+    }
+
+    // Every value given for `key`, in order.
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        self.iter().filter(|(k, _)| *k == key).map(|(_, value)| value).collect()
+    }
+}
+
+impl fmt::Display for RawFields {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for field in &self.fields {
+            write!(f, "{}{}", field.leading, field.key)?;
+            if let Some(value) = &field.value {
+                write!(f, ":{}", value)?;
+            }
+        }
+
+        write!(f, "{}", self.trailing)
+    }
+}
+
+// Which rule a field broke.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Rule {
+    Missing,
+    OutOfRange { min: u64, max: u64, unit: String },
+    // What the value should have looked like.
+    BadFormat(String),
+    UnknownKey,
+    // The key was given more than once, always with the same value.
+    DuplicateKey,
+    // The key was given more than once with different values, all listed in
+    // order. The last one is used.
+    ConflictingValues(Vec<String>),
+    // A token which isn't `key:value`.
+    Malformed,
+}
+
+impl Rule {
+    // The rule without its details, for grouping violations.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Rule::Missing => "missing",
+            Rule::OutOfRange { .. } => "out of range",
+            Rule::BadFormat(_) => "bad format",
+            Rule::UnknownKey => "unknown key",
+            Rule::DuplicateKey => "duplicate key",
+            Rule::ConflictingValues(_) => "conflicting values",
+            Rule::Malformed => "malformed",
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rule::Missing => write!(f, "missing"),
+            Rule::OutOfRange { min, max, unit } => write!(f, "out of range ({}{unit} to {}{unit})", min, max, unit = unit),
+            Rule::BadFormat(expected) => write!(f, "bad format (expected {})", expected),
+            Rule::UnknownKey => write!(f, "unknown key"),
+            Rule::DuplicateKey => write!(f, "given more than once"),
+            Rule::ConflictingValues(values) => write!(f, "given conflicting values ({})", values.join(", ")),
+            Rule::Malformed => write!(f, "not `key:value`"),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Violation {
+    pub field: String,
+    // None if the field is missing.
+    pub value: Option<String>,
+    pub rule: Rule,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.value {
+            Some(value) => write!(f, "{}:{} is {}", self.field, value, self.rule),
+            None => write!(f, "{} is {}", self.field, self.rule),
+        }
+    }
 }
 
 impl Passport {
+    // Whether every field the puzzle's policy requires is given, whatever
+    // its value.
     pub fn is_somewhat_valid(&self) -> bool {
-        self.byr.is_some() &&
-            self.iyr.is_some() &&
-            self.eyr.is_some() &&
-            self.hgt != Height::Invalid &&
-            self.hcl.is_some() &&
-            self.ecl.is_some() &&
-            self.pid.is_some()
+        self.has_required_fields(&DEFAULT_POLICY)
+    }
+
+    pub fn has_required_fields(&self, policy: &Policy) -> bool {
+        policy.fields.iter().all(|field| !field.required || self.raw.get(&field.key).is_some())
     }
 
     pub fn is_valid(&self) -> bool {
-        self.byr.is_some() && self.byr.unwrap().is_between(1920, 2002) &&
-            self.iyr.is_some() && self.iyr.unwrap().is_between(2010, 2020) &&
-            self.eyr.is_some() && self.eyr.unwrap().is_between(2020, 2030) &&
-            self.hgt.is_valid() &&
-            self.hcl.is_some() &&
-            self.ecl.is_some() &&
-            self.pid.is_some()
+        self.validate().is_empty()
     }
-}
 
-type Year = u16;
+    // The record as read; displaying it reproduces the input exactly.
+    pub fn raw(&self) -> &RawFields {
+        &self.raw
+    }
 
-trait YearMethods {
-    fn parse(input: &str) -> Option<Year>;
-    fn is_between(&self, a: u16, b: u16) -> bool;
+    // Every rule of the puzzle's policy the passport breaks.
+    pub fn validate(&self) -> Vec<Violation> {
+        self.validate_with(&DEFAULT_POLICY)
+    }
+
+    pub fn validate_with(&self, policy: &Policy) -> Vec<Violation> {
+        policy.check(&self.raw)
+    }
 }
 
-impl YearMethods for Year {
-    fn parse(input: &str) -> Option<Year> {
-        match input.parse::<u16>() {
-            Ok(v) => Some(v),
-            Err(_) => None
+// Writes every violation in the invalid passports as a table, one passport at
+// a time, numbering them by their position in the batch. Rows can't wait to
+// line up with ones not read yet, so each column is as wide as the widest cell
+// written to it so far, starting with the policy's keys for fields.
+pub struct RejectionWriter<'a, W: Write> {
+    out: W,
+    policy: &'a Policy,
+    widths: [usize; 3],
+    written: usize,
+}
+
+impl<'a, W: Write> RejectionWriter<'a, W> {
+    pub fn new(out: W, policy: &'a Policy) -> io::Result<RejectionWriter<'a, W>> {
+        let keys = policy.fields.iter().map(|field| field.key.chars().count()).max().unwrap_or(0);
+        let mut writer = RejectionWriter { out, policy, widths: [0, keys, 0], written: 0 };
+        writer.write_row(["passport", "field", "value", "problem"])?;
+
+        Ok(writer)
+    }
+
+    fn write_row(&mut self, row: [&str; 4]) -> io::Result<()> {
+        for (width, cell) in self.widths.iter_mut().zip(&row) {
+            *width = (*width).max(cell.chars().count());
         }
+
+        let [w0, w1, w2] = self.widths;
+        writeln!(self.out, "{:<w0$}  {:<w1$}  {:<w2$}  {}", row[0], row[1], row[2], row[3], w0 = w0, w1 = w1, w2 = w2)
     }
 
-    fn is_between(&self, a: u16, b: u16) -> bool {
-        (a..=b).contains(&self)
+    pub fn write(&mut self, passport: &Passport) -> io::Result<()> {
+        self.written += 1;
+        let number = self.written.to_string();
+
+        for violation in passport.validate_with(self.policy) {
+            let problem = violation.rule.to_string();
+            self.write_row([&number, &violation.field, violation.value.as_deref().unwrap_or("-"), &problem])?;
+        }
+
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
     }
 }
 
-#[derive(Clone, PartialEq, Eq, Debug)]
-enum Height {
-    Invalid,
-    In(u8),
-    Cm(u8)
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct PassportStats {
+    pub total: usize,
+    pub valid: usize,
+    pub invalid: usize,
+    // The number of invalid passports breaking each kind of rule for each
+    // field, keyed by field and `Rule::kind`. Keys which aren't in the policy
+    // and malformed tokens all come under None, so there are only as many
+    // reasons as the policy allows, whatever the input.
+    pub reasons: BTreeMap<(Option<String>, &'static str), usize>,
 }
 
-impl Default for Height {
-    fn default() -> Self {
-        Height::Invalid
+impl PassportStats {
+    fn add(&mut self, policy: &Policy, violations: &[Violation]) {
+        self.total += 1;
+
+        if violations.is_empty() {
+            self.valid += 1;
+            return;
+        }
+
+        self.invalid += 1;
+
+        let mut reasons: Vec<(Option<&str>, &'static str)> = violations.iter()
+            .map(|violation| {
+                let field = policy.fields.iter()
+                    .find(|field| field.key == violation.field && violation.rule != Rule::Malformed)
+                    .map(|field| field.key.as_str());
+
+                (field, violation.rule.kind())
+            })
+            .collect();
+        reasons.sort_unstable();
+        reasons.dedup();
+
+        for (field, kind) in reasons {
+            *self.reasons.entry((field.map(str::to_string), kind)).or_default() += 1;
+        }
     }
 }
 
-impl Height {
-    fn parse(input: &str) -> Height {
-        if let Some(captures) = regex_captures!(r"(\d+)(cm|in)", input) {
-            match captures[1].parse::<u8>() {
-                Ok(v) => match &captures[2] {
-                    "cm" => Height::Cm(v),
-                    "in" => Height::In(v),
-                    &_ => Height::Invalid
-                },
-                Err(_) => Height::Invalid
+impl fmt::Display for PassportStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} passports, {} valid, {} invalid", self.total, self.valid, self.invalid)?;
+
+        for ((field, kind), count) in &self.reasons {
+            if let Some(field) = field {
+                writeln!(f, "  {} {}: {}", field, kind, count)?;
             }
-        } else {
-            Height::Invalid
         }
-    }
 
-    fn is_valid(&self) -> bool {
-        match self {
-            Height::Cm(cm) => (150..=193).contains(cm),
-            Height::In(ins) => (59..=76).contains(ins),
-            Height::Invalid => false,
+        for ((field, kind), count) in &self.reasons {
+            if field.is_none() {
+                writeln!(f, "  other keys {}: {}", kind, count)?;
+            }
         }
+
+        Ok(())
     }
 }
 
-#[derive(Clone, PartialEq, Eq, Debug)]
-struct HairColor { inner: String }
+// Reads passports one record at a time, validating each against a policy and
+// keeping count of the results as it goes.
+pub struct PassportReader<'a, T: BufRead> {
+    records: Peekable<RecordReader<T>>,
+    policy: &'a Policy,
+    stats: PassportStats,
+}
 
-impl HairColor {
-    fn parse(input: &str) -> Option<HairColor> {
-        regex_captures!(r"^#[0-9a-f]{6}$", input)
-            .map(|c| HairColor { inner: c[0].to_string() })
+impl<T: BufRead> PassportReader<'static, T> {
+    pub fn new(reader: T) -> PassportReader<'static, T> {
+        PassportReader::with_policy(reader, &DEFAULT_POLICY)
     }
 }
 
-#[derive(Clone, PartialEq, Eq, Debug)]
-struct PassportID { inner: String }
-impl PassportID {
-    fn parse(input: &str) -> Option<PassportID> {
-        regex_captures!(r"^[0-9]{9}$", input)
-            .map(|c| PassportID { inner: c[0].to_string() })
+impl<'a, T: BufRead> PassportReader<'a, T> {
+    pub fn with_policy(reader: T, policy: &'a Policy) -> PassportReader<'a, T> {
+        PassportReader { records: RecordReader::new(reader).peekable(), policy, stats: PassportStats::default() }
+    }
+
+    // Counts for the passports read so far.
+    pub fn stats(&self) -> &PassportStats {
+        &self.stats
     }
 }
 
+impl<'a, T: BufRead> Iterator for PassportReader<'a, T> {
+    type Item = Result<Passport, Error>;
+
+    // Fails only on I/O errors. A record with tokens which aren't
+    // `key:value` is still a passport, just an invalid one.
+    fn next(&mut self) -> Option<Self::Item> {
+        let is_blank = |record: &str| record.trim().is_empty();
+
+        // Blank lines after the one ending a record would otherwise read as
+        // empty records. They are kept with the record before them, or the
+        // first record if they start the batch, so it is still written back
+        // exactly as read.
+        let mut record = String::new();
+        while is_blank(&record) {
+            match self.records.next()? {
+                Ok(text) => record.push_str(&text),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+
+        while let Some(Ok(text)) = self.records.peek() {
+            if !is_blank(text) {
+                break;
+            }
+
+            record.push_str(text);
+            self.records.next();
+        }
+
+        let passport = parse_passport(&record);
 
-#[derive(Clone, FromStr, PartialEq, Eq, Debug)]
-#[allow(non_camel_case_types)]
-enum EyeColor { amb, blu, brn, gry, grn, hzl, oth }
+        self.stats.add(self.policy, &passport.validate_with(self.policy));
+        Some(Ok(passport))
+    }
+}
 
+pub fn parse_passports<T: BufRead>(reader: T) -> Result<Vec<Passport>, Error> {
+    PassportReader::new(reader).collect()
+}
 
-pub fn parse_passports<T: BufRead>(reader: T) -> Vec<Passport> {
+// Parses a single blank-line-separated record.
+fn parse_passport(record: &str) -> Passport {
     lazy_static! {
-        static ref FIELD_RE: Regex = Regex::new(r"(\w{3}):(\S*)").unwrap();
+        static ref FIELD_RE: Regex = Regex::new(r"^([^:\s]+):(\S*)$").unwrap();
+        static ref TOKEN_RE: Regex = Regex::new(r"\S+").unwrap();
     }
 
-    let record_reader = RecordReader::new(reader);
+    let mut passport = Passport::default();
+    // Where the last field ended, and the current line starts, in `record`.
+    let mut field_end = 0;
+    let mut line_start = 0;
 
-    record_reader.map(|record| {
-        let mut passport = Passport::default();
+    for line in record.split_inclusive('\n') {
+        let text = line.trim_end_matches(&['\r', '\n'][..]);
 
-        for captures in FIELD_RE.captures_iter(record.as_str()) {
-            let key: &str = &captures[1];
-            let value: &str = &captures[2];
+        for token in TOKEN_RE.find_iter(text) {
+            let leading = &record[field_end..line_start + token.start()];
 
-            match key {
-                "byr" => passport.byr = Year::parse(value),
-                "iyr" => passport.iyr = Year::parse(value),
-                "eyr" => passport.eyr = Year::parse(value),
-                "hgt" => passport.hgt = Height::parse(value),
-                "hcl" => passport.hcl = HairColor::parse(value),
-                "ecl" => passport.ecl = value.parse::<EyeColor>().ok(),
-                "pid" => passport.pid = PassportID::parse(value),
-                "cid" => passport.cid = Some(value.to_string()),
-                &_ => {}
+            match FIELD_RE.captures(token.as_str()) {
+                Some(captures) => passport.raw.push(leading, &captures[1], Some(&captures[2])),
+                None => passport.raw.push(leading, token.as_str(), None),
             }
+
+            field_end = line_start + token.end();
         }
 
-        passport
-    }).collect()
+        line_start += line.len();
+    }
+
+    passport.raw.trailing = record[field_end..].to_string();
+
+    passport
+}
+
+pub struct Day04;
+
+impl Solution for Day04 {
+    fn part_a(&self, input: &mut dyn BufRead) -> Result<String, Error> {
+        let mut count = 0;
+
+        for passport in PassportReader::new(input) {
+            if passport?.is_somewhat_valid() {
+                count += 1;
+            }
+        }
+
+        Ok(count.to_string())
+    }
+
+    fn part_b(&self, input: &mut dyn BufRead) -> Result<String, Error> {
+        let mut reader = PassportReader::new(input);
+
+        for passport in &mut reader {
+            passport?;
+        }
+
+        Ok(reader.stats().valid.to_string())
+    }
 }
 
 #[test]
@@ -171,22 +446,178 @@ hgt:179cm
 hcl:#cfa07d eyr:2025 pid:166559648
 iyr:2011 ecl:brn hgt:59in");
 
-    let passports = parse_passports(reader);
+    let passports = parse_passports(reader).unwrap();
 
     assert_eq!(
-        Passport {
-            byr: Some(1937),
-            iyr: Some(2017),
-            eyr: Some(2020),
-            hgt: Height::Cm(183),
-            hcl: Some(HairColor { inner: "#fffffd".to_string() }),
-            ecl: Some(EyeColor::gry),
-            pid: Some(PassportID { inner: "860033327".to_string() }), // TODO
-            cid: Some("147".to_string()), // TODO
-        },
-        passports[0]
+        vec!["ecl", "pid", "eyr", "hcl", "byr", "iyr", "cid", "hgt"],
+        passports[0].raw().keys()
     );
+    assert_eq!(Some("#fffffd"), passports[0].raw().get("hcl"));
+    assert_eq!(None, passports[2].raw().get("cid"));
 
     assert_eq!(4, passports.len());
+    assert_eq!(
+        vec![true, false, true, false],
+        passports.iter().map(Passport::is_somewhat_valid).collect::<Vec<bool>>()
+    );
+
+    // Only presence counts, so values part B rejects are fine.
+    let passport = parse_passport("byr:0 iyr:x eyr:2020 hgt:3ft hcl:red ecl:zzz pid:1");
+    assert!(passport.is_somewhat_valid());
+    assert!(!passport.is_valid());
+}
+
+#[test]
+fn test_raw_fields() {
+    use std::io::Cursor;
+    let input = "\
+  ecl:gry\tpid:860033327   eyr:2020 ecl:gry
+byr:1937 xyz:1 byr:1938  \r
+
+hcl:#ae17e1
+
+";
+
+    let passports = parse_passports(Cursor::new(input)).unwrap();
+
+    assert_eq!(input, passports.iter().map(|p| p.raw().to_string()).collect::<String>());
+    assert_eq!(vec!["1937", "1938"], passports[0].raw().get_all("byr"));
+    assert_eq!(Some("1938"), passports[0].raw().get("byr"));
+    assert_eq!(7, passports[0].raw().len());
+    assert!(passports[0].validate().iter().all(|violation| violation.rule == Rule::Missing));
+
+    let strict = Policy { reject_unknown_keys: true, reject_repeated_keys: true, ..Policy::default() };
+    let problems: Vec<String> = passports[0].validate_with(&strict).iter().skip(3).map(|v| v.to_string()).collect();
+    assert_eq!(
+        vec![
+            "xyz:1 is unknown key",
+            "ecl:gry is given more than once",
+            "byr:1938 is given conflicting values (1937, 1938)",
+        ],
+        problems
+    );
+
+    // Extra blank lines stay with a record rather than making empty ones.
+    let input = "\n\necl:gry\n\n\n\n  \n\nbyr:1937\n\n\n";
+    let passports = parse_passports(Cursor::new(input)).unwrap();
+    assert_eq!(2, passports.len());
+    assert_eq!(input, passports.iter().map(|p| p.raw().to_string()).collect::<String>());
+
+    // Keys of any length are kept, for the policy to report as unknown.
+    let passports = parse_passports(Cursor::new("ecl:gry country:US x-ray:a:b")).unwrap();
+    assert_eq!(Some("US"), passports[0].raw().get("country"));
+    assert_eq!(Some("a:b"), passports[0].raw().get("x-ray"));
+
+    let unknown: Vec<String> = passports[0].validate_with(&strict).into_iter()
+        .filter(|violation| violation.rule == Rule::UnknownKey)
+        .map(|violation| violation.field)
+        .collect();
+    assert_eq!(vec!["country", "x-ray"], unknown);
 }
 
+#[test]
+fn test_validate() {
+    use std::io::Cursor;
+    let reader = Cursor::new("\
+eyr:1972 cid:100
+hcl:#18171d ecl:amb hgt:170 pid:186cm iyr:2018 byr:1926
+
+pid:087499704 hgt:74in ecl:grn iyr:2012 eyr:2030 byr:1980
+hcl:#623a2f
+
+hgt:59cm ecl:zzz eyr:2038 hcl:74454a iyr:2023
+pid:3556412378 byr:2007 foo:bar");
+
+    let passports = parse_passports(reader).unwrap();
+
+    let violation = |field: &str, value: &str, rule: Rule| Violation {
+        field: field.to_string(),
+        value: Some(value.to_string()),
+        rule,
+    };
+
+    assert_eq!(
+        vec![
+            violation("eyr", "1972", Rule::OutOfRange { min: 2020, max: 2030, unit: "".to_string() }),
+            violation("hgt", "170", Rule::BadFormat("a number followed by cm or in".to_string())),
+            violation("pid", "186cm", Rule::BadFormat("nine digits".to_string())),
+        ],
+        passports[0].validate()
+    );
+    assert!(passports[1].is_valid());
+    assert_eq!(
+        vec!["byr", "iyr", "eyr", "hgt", "hcl", "ecl", "pid"],
+        passports[2].validate().iter().map(|v| v.field.as_str()).collect::<Vec<&str>>()
+    );
+
+    let passport = parse_passport("byr:1980");
+    assert_eq!("hgt is missing", passport.validate()[2].to_string());
+
+    let mut writer = RejectionWriter::new(vec![], &DEFAULT_POLICY).unwrap();
+    for passport in &passports {
+        writer.write(passport).unwrap();
+    }
+    let table = String::from_utf8(writer.into_inner()).unwrap();
+    assert!(table.starts_with("passport  field  value  problem\n"));
+    assert!(table.contains("\n3         hgt    59cm   out of range (150cm to 193cm)\n"));
+    assert!(table.ends_with("\n3         pid    3556412378  bad format (expected nine digits)\n"));
+    assert_eq!(11, table.lines().count());
+}
+
+#[test]
+fn test_malformed_tokens() {
+    use std::io::Cursor;
+    let input = "\
+ecl:gry pid:860033327
+
+iyr:2013 ecl:amb
+hcl:#cfa07d byr1929";
+
+    // Only the record with the bad token is affected.
+    let passports = parse_passports(Cursor::new(input)).unwrap();
+    assert_eq!(2, passports.len());
+    assert_eq!(input, passports.iter().map(|p| p.raw().to_string()).collect::<String>());
+    assert_eq!(vec!["byr1929"], passports[1].raw().malformed().collect::<Vec<&str>>());
+    assert_eq!(3, passports[1].raw().len());
+
+    let violations = passports[1].validate();
+    assert_eq!("byr1929 is not `key:value`", violations.last().unwrap().to_string());
+    assert!(!passports[0].validate().iter().any(|violation| violation.rule == Rule::Malformed));
+}
+
+#[test]
+fn test_passport_reader() {
+    use std::io::Cursor;
+    let mut reader = PassportReader::new(Cursor::new("\
+pid:087499704 hgt:74in ecl:grn iyr:2012 eyr:2030 byr:1980
+hcl:#623a2f
+
+eyr:1972 cid:100 hgt:170
+hcl:#18171d ecl:amb pid:186cm iyr:2018 byr:1926
+
+hgt:59cm ecl:zzz eyr:2038 hcl:74454a iyr:2023
+pid:3556412378
+
+hcl:#cfa07d byr1929
+
+byr:1937"));
+
+    assert!(reader.next().unwrap().unwrap().is_valid());
+    assert_eq!((1, 1, 0), (reader.stats().total, reader.stats().valid, reader.stats().invalid));
+
+    assert!(reader.next().unwrap().is_ok());
+    assert!(reader.next().unwrap().is_ok());
+    assert!(!reader.next().unwrap().unwrap().is_valid());
+    assert_eq!((4, 1, 3), (reader.stats().total, reader.stats().valid, reader.stats().invalid));
+
+    assert!(reader.next().unwrap().is_ok());
+    assert!(reader.next().is_none());
+
+    let stats = reader.stats();
+    assert_eq!((5, 1, 4), (stats.total, stats.valid, stats.invalid));
+    assert_eq!(Some(&2), stats.reasons.get(&(Some("byr".to_string()), "missing")));
+    assert_eq!(Some(&1), stats.reasons.get(&(Some("hgt".to_string()), "bad format")));
+    assert_eq!(Some(&2), stats.reasons.get(&(Some("eyr".to_string()), "out of range")));
+    assert!(stats.to_string().starts_with("5 passports, 1 valid, 4 invalid\n  byr missing: 2\n"));
+    assert!(stats.to_string().ends_with("\n  other keys malformed: 1\n"));
+}
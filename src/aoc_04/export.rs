@@ -0,0 +1,201 @@
+// Writing passports out for reporting, or back into a batch file.
+
+use std::io::{self, Write};
+
+use serde::{Serialize, Serializer};
+
+use super::policy::Policy;
+use super::{Passport, Violation};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Format {
+    // One JSON object per passport: `{"fields": {...}, "valid": ...,
+    // "violations": [...]}`, with a value or null for each of the policy's
+    // fields.
+    JsonLines,
+    // The same as a header row then one row per passport, with violations
+    // separated by `; `.
+    Csv,
+    // Blank-line-separated `key:value` records, exactly as they were read.
+    Batch,
+}
+
+// serde_json sorts the keys of its own maps, so records are built from these
+// to keep them in order: fields as the policy lists them, the same as the CSV
+// columns.
+#[derive(Serialize)]
+struct JsonRecord<'a> {
+    fields: JsonFields<'a>,
+    valid: bool,
+    violations: Vec<JsonViolation<'a>>,
+}
+
+struct JsonFields<'a>(Vec<(&'a str, Option<&'a str>)>);
+
+impl Serialize for JsonFields<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().copied())
+    }
+}
+
+#[derive(Serialize)]
+struct JsonViolation<'a> {
+    field: &'a str,
+    value: Option<&'a str>,
+    problem: String,
+}
+
+impl<'a> From<&'a Violation> for JsonViolation<'a> {
+    fn from(violation: &'a Violation) -> JsonViolation<'a> {
+        JsonViolation { field: &violation.field, value: violation.value.as_deref(), problem: violation.rule.to_string() }
+    }
+}
+
+// Quotes a CSV cell if it needs it.
+fn csv_cell(text: &str) -> String {
+    if text.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+pub struct PassportWriter<'a, W: Write> {
+    out: W,
+    format: Format,
+    policy: &'a Policy,
+    written: usize,
+    // What has to be written before the next batch record, so the last one
+    // ends with a blank line.
+    separator: &'static str,
+}
+
+impl<'a, W: Write> PassportWriter<'a, W> {
+    pub fn new(out: W, format: Format, policy: &'a Policy) -> PassportWriter<'a, W> {
+        PassportWriter { out, format, policy, written: 0, separator: "" }
+    }
+
+    pub fn write(&mut self, passport: &Passport) -> io::Result<()> {
+        let violations = passport.validate_with(self.policy);
+        let values = self.policy.fields.iter().map(|field| passport.raw().get(&field.key));
+
+        match self.format {
+            Format::JsonLines => {
+                let record = JsonRecord {
+                    fields: JsonFields(self.policy.fields.iter().map(|field| field.key.as_str()).zip(values).collect()),
+                    valid: violations.is_empty(),
+                    violations: violations.iter().map(JsonViolation::from).collect(),
+                };
+
+                serde_json::to_writer(&mut self.out, &record)?;
+                writeln!(self.out)?;
+            }
+            Format::Csv => {
+                if self.written == 0 {
+                    let header: Vec<String> = self.policy.fields.iter().map(|field| csv_cell(&field.key)).collect();
+                    writeln!(self.out, "{},valid,violations", header.join(","))?;
+                }
+
+                let cells: Vec<String> = values.map(|value| csv_cell(value.unwrap_or(""))).collect();
+                let violations: Vec<String> = violations.iter().map(Violation::to_string).collect();
+
+                writeln!(self.out, "{},{},{}", cells.join(","), violations.is_empty(), csv_cell(&violations.join("; ")))?;
+            }
+            Format::Batch => {
+                let record = passport.raw().to_string();
+                write!(self.out, "{}{}", self.separator, record)?;
+
+                // The record read last from a file has no blank line after
+                // it, which is only added if another record follows, so a
+                // single file's records are written back byte for byte.
+                self.separator = if record.ends_with("\n\n") {
+                    ""
+                } else if record.ends_with('\n') {
+                    "\n"
+                } else {
+                    "\n\n"
+                };
+            }
+        }
+
+        self.written += 1;
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+pub fn write_passports<'a, W: Write>(passports: impl IntoIterator<Item=&'a Passport>, format: Format,
+                                     policy: &Policy, out: W) -> io::Result<()> {
+    let mut writer = PassportWriter::new(out, format, policy);
+
+    for passport in passports {
+        writer.write(passport)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+fn example_passports() -> Vec<Passport> {
+    super::parse_passports(io::Cursor::new("\
+ecl:gry pid:860033327 eyr:2020 hcl:#fffffd
+byr:1937 iyr:2017 cid:147 hgt:183cm
+
+iyr:2013 ecl:amb cid:350 eyr:2023 pid:028048884
+hcl:#cfa07d byr:1929 zzz:\"a,b\"")).unwrap()
+}
+
+#[test]
+fn test_export() {
+    use serde_json::{json, Value};
+
+    let passports = example_passports();
+    let policy = Policy { reject_unknown_keys: true, ..Policy::default() };
+
+    let export = |format: Format| {
+        let mut buf = vec![];
+        write_passports(&passports, format, &policy, &mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    };
+
+    let json = export(Format::JsonLines);
+    assert_eq!(2, json.lines().count());
+    assert!(json.starts_with(r##"{"fields":{"byr":"1937","iyr":"2017","eyr":"2020","hgt":"183cm","hcl":"#fffffd","ecl":"gry","pid":"860033327","cid":"147"},"valid":true,"violations":[]}"##));
+    assert!(json.contains(r#""hgt":null,"#));
+    assert!(json.contains(r#"{"field":"zzz","value":"\"a,b\"","problem":"unknown key"}"#));
+
+    // Fields named like the record's own keys stay separate from them.
+    let policy = Policy::from_json(r#"{"reject_unknown_keys": true, "fields": [{"key": "valid"}, {"key": "violations", "required": true}]}"#).unwrap();
+    let mut buf = vec![];
+    write_passports(&example_passports()[..1], Format::JsonLines, &policy, &mut buf).unwrap();
+
+    let record: Value = serde_json::from_slice(&buf).unwrap();
+    assert_eq!(json!({"valid": null, "violations": null}), record["fields"]);
+    assert_eq!(json!(false), record["valid"]);
+    assert_eq!(9, record["violations"].as_array().unwrap().len());
+
+    let csv = export(Format::Csv);
+    let rows: Vec<&str> = csv.lines().collect();
+    assert_eq!("byr,iyr,eyr,hgt,hcl,ecl,pid,cid,valid,violations", rows[0]);
+    assert_eq!("1937,2017,2020,183cm,#fffffd,gry,860033327,147,true,", rows[1]);
+    assert_eq!(
+        r#"1929,2013,2023,,#cfa07d,amb,028048884,350,false,"hgt is missing; zzz:""a,b"" is unknown key""#,
+        rows[2]
+    );
+
+    // The last record had no blank line after it, so writing it twice needs
+    // one added in between.
+    let mut buf = vec![];
+    write_passports(passports.iter().chain(&passports), Format::Batch, &policy, &mut buf).unwrap();
+    let batch = String::from_utf8(buf).unwrap();
+
+    let read = super::parse_passports(io::Cursor::new(&batch)).unwrap();
+    assert_eq!(4, read.len());
+    assert_eq!(passports[1].raw().iter().collect::<Vec<_>>(), read[3].raw().iter().collect::<Vec<_>>());
+
+    let original: String = passports.iter().map(|passport| passport.raw().to_string()).collect();
+    assert_eq!(original, export(Format::Batch));
+}
@@ -0,0 +1,344 @@
+// Declarative passport rules, read from JSON:
+//
+//     {
+//       "reject_unknown_keys": true,
+//       "reject_repeated_keys": false,
+//       "fields": [
+//         {"key": "byr", "required": true, "digits": 4, "range": [1920, 2002]},
+//         {"key": "hgt", "required": true, "units": {"cm": [150, 193], "in": [59, 76]}},
+//         {"key": "hcl", "required": true, "pattern": "^#[0-9a-f]{6}$", "expected": "# and six hex digits"},
+//         {"key": "ecl", "required": true, "one_of": ["amb", "blu", "brn"]},
+//         {"key": "cid"}
+//       ]
+//     }
+//
+// A field takes at most one of `range` (which `digits` can go with), `units`,
+// `pattern` or `one_of`, and accepts any value if it has none. Keys which
+// aren't listed are unknown, and are only a problem with
+// `reject_unknown_keys`; likewise keys given more than once with
+// `reject_repeated_keys`. Both are off unless given. The puzzle's rules are
+// in default_policy.json.
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::error::Error;
+
+use super::{RawFields, Rule, Violation};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonPolicy {
+    #[serde(default)]
+    reject_unknown_keys: bool,
+    #[serde(default)]
+    reject_repeated_keys: bool,
+    fields: Vec<JsonField>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonField {
+    key: String,
+    #[serde(default)]
+    required: bool,
+    digits: Option<usize>,
+    range: Option<(u64, u64)>,
+    units: Option<BTreeMap<String, (u64, u64)>>,
+    pattern: Option<String>,
+    expected: Option<String>,
+    one_of: Option<Vec<String>>,
+}
+
+#[derive(Clone, Debug)]
+pub enum Check {
+    Any,
+    // A whole number, with exactly `digits` digits if given.
+    Number { digits: Option<usize>, min: u64, max: u64 },
+    // A whole number followed by one of the units, each with its own range.
+    Units(Vec<(String, u64, u64)>),
+    // `expected` describes what the pattern matches, for violations.
+    Pattern { regex: Regex, expected: String },
+    OneOf(Vec<String>),
+}
+
+fn in_range(number: &str, min: u64, max: u64, unit: &str) -> Result<(), Rule> {
+    // Digits too long for a u64 are out of range too.
+    match number.parse::<u64>() {
+        Ok(number) if (min..=max).contains(&number) => Ok(()),
+        _ => Err(Rule::OutOfRange { min, max, unit: unit.to_string() }),
+    }
+}
+
+fn is_number(text: &str) -> bool {
+    !text.is_empty() && text.bytes().all(|b| b.is_ascii_digit())
+}
+
+impl Check {
+    pub fn check(&self, value: &str) -> Result<(), Rule> {
+        match self {
+            Check::Any => Ok(()),
+            Check::Number { digits, min, max } => match digits {
+                Some(digits) if !is_number(value) || value.len() != *digits =>
+                    Err(Rule::BadFormat(format!("{} digits", digits))),
+                None if !is_number(value) => Err(Rule::BadFormat("a number".to_string())),
+                _ => in_range(value, *min, *max, ""),
+            },
+            Check::Units(units) => {
+                let unit = units.iter().find(|(unit, _, _)| {
+                    matches!(value.strip_suffix(unit.as_str()), Some(number) if is_number(number))
+                });
+
+                match unit {
+                    Some((unit, min, max)) => in_range(&value[..value.len() - unit.len()], *min, *max, unit),
+                    None => {
+                        let names: Vec<&str> = units.iter().map(|(unit, _, _)| unit.as_str()).collect();
+                        Err(Rule::BadFormat(format!("a number followed by {}", names.join(" or "))))
+                    }
+                }
+            }
+            Check::Pattern { regex, expected } if !regex.is_match(value) => Err(Rule::BadFormat(expected.clone())),
+            Check::Pattern { .. } => Ok(()),
+            Check::OneOf(values) if !values.iter().any(|allowed| allowed == value) =>
+                Err(Rule::BadFormat(format!("one of {}", values.join(", ")))),
+            Check::OneOf(_) => Ok(()),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FieldPolicy {
+    pub key: String,
+    pub required: bool,
+    pub check: Check,
+}
+
+impl TryFrom<JsonField> for FieldPolicy {
+    type Error = String;
+
+    fn try_from(field: JsonField) -> Result<FieldPolicy, String> {
+        let JsonField { key, required, digits, range, units, pattern, expected, one_of } = field;
+
+        let given = [range.is_some(), units.is_some(), pattern.is_some(), one_of.is_some()];
+        if given.iter().filter(|given| **given).count() > 1 {
+            return Err(format!("{} has more than one of range, units, pattern and one_of", key));
+        }
+
+        if digits.is_some() && units.is_some() {
+            return Err(format!("{} has digits, which only go with a range", key));
+        }
+
+        if expected.is_some() && pattern.is_none() {
+            return Err(format!("{} has expected without a pattern", key));
+        }
+
+        let ranges = range.iter().map(|range| ("range".to_string(), range))
+            .chain(units.iter().flatten().map(|(unit, range)| (format!("the {} range", unit), range)));
+
+        for (name, (min, max)) in ranges {
+            if min > max {
+                return Err(format!("{} has {} going down from {} to {}", key, name, min, max));
+            }
+        }
+
+        let check = if let Some((min, max)) = range {
+            Check::Number { digits, min, max }
+        } else if let Some(digits) = digits {
+            Check::Number { digits: Some(digits), min: 0, max: u64::MAX }
+        } else if let Some(units) = units {
+            Check::Units(units.into_iter().map(|(unit, (min, max))| (unit, min, max)).collect())
+        } else if let Some(pattern) = pattern {
+            let regex = Regex::new(&pattern).map_err(|e| format!("{} has an invalid pattern: {}", key, e))?;
+            let expected = expected.unwrap_or_else(|| format!("a match for {}", pattern));
+
+            Check::Pattern { regex, expected }
+        } else if let Some(values) = one_of {
+            Check::OneOf(values)
+        } else {
+            Check::Any
+        };
+
+        Ok(FieldPolicy { key, required, check })
+    }
+}
+
+// An error about the `idx`th field. serde doesn't keep positions for values
+// once they are read, so it is located by its place in the list.
+fn field_error(idx: usize, reason: String) -> Error {
+    Error::invalid(format!("fields[{}]", idx), reason)
+}
+
+#[derive(Clone, Debug)]
+pub struct Policy {
+    // Whether keys which aren't in `fields` make a passport invalid.
+    pub reject_unknown_keys: bool,
+    // Whether a key given more than once makes a passport invalid, even with
+    // the same value each time.
+    pub reject_repeated_keys: bool,
+    // In the order violations are reported.
+    pub fields: Vec<FieldPolicy>,
+}
+
+lazy_static! {
+    pub static ref DEFAULT_POLICY: Policy = Policy::from_json(include_str!("default_policy.json")).unwrap();
+}
+
+impl Default for Policy {
+    fn default() -> Policy {
+        DEFAULT_POLICY.clone()
+    }
+}
+
+impl Policy {
+    pub fn from_json(input: &str) -> Result<Policy, Error> {
+        let json: JsonPolicy = serde_json::from_str(input).map_err(|e| Error::json(&e, input))?;
+        let mut fields: Vec<FieldPolicy> = vec![];
+
+        for (idx, field) in json.fields.into_iter().enumerate() {
+            let field = FieldPolicy::try_from(field).map_err(|reason| field_error(idx, reason))?;
+
+            if fields.iter().any(|earlier| earlier.key == field.key) {
+                return Err(field_error(idx, format!("{} is listed twice", field.key)));
+            }
+
+            fields.push(field);
+        }
+
+        Ok(Policy {
+            reject_unknown_keys: json.reject_unknown_keys,
+            reject_repeated_keys: json.reject_repeated_keys,
+            fields,
+        })
+    }
+
+    // Every rule broken by a passport with these fields, where later values
+    // for a key replace earlier ones: the policy's fields in order, then
+    // unknown keys and keys given more than once if the policy rejects them,
+    // each in the order first given, then tokens which aren't `key:value`.
+    pub fn check(&self, raw: &RawFields) -> Vec<Violation> {
+        let mut violations = vec![];
+
+        for field in &self.fields {
+            let value = raw.get(&field.key);
+
+            let result = match value {
+                Some(value) => field.check.check(value),
+                None if field.required => Err(Rule::Missing),
+                None => Ok(()),
+            };
+
+            if let Err(rule) = result {
+                violations.push(Violation { field: field.key.clone(), value: value.map(str::to_string), rule });
+            }
+        }
+
+        for key in raw.keys().into_iter().filter(|_| self.reject_unknown_keys) {
+            if !self.fields.iter().any(|field| field.key == key) {
+                violations.push(Violation { field: key.to_string(), value: raw.get(key).map(str::to_string), rule: Rule::UnknownKey });
+            }
+        }
+
+        for key in raw.keys().into_iter().filter(|_| self.reject_repeated_keys) {
+            let values = raw.get_all(key);
+
+            let rule = if values.len() < 2 {
+                continue;
+            } else if values.iter().all(|value| *value == values[0]) {
+                Rule::DuplicateKey
+            } else {
+                Rule::ConflictingValues(values.iter().map(|value| value.to_string()).collect())
+            };
+
+            violations.push(Violation { field: key.to_string(), value: raw.get(key).map(str::to_string), rule });
+        }
+
+        for token in raw.malformed() {
+            violations.push(Violation { field: token.to_string(), value: None, rule: Rule::Malformed });
+        }
+
+        violations
+    }
+}
+
+#[test]
+fn test_policy() {
+    let mut policy = Policy::from_json(r#"{"reject_unknown_keys": true, "reject_repeated_keys": true, "fields": [
+        {"key": "age", "required": true, "range": [18, 120]},
+        {"key": "len", "units": {"mm": [1, 999], "m": [1, 5]}},
+        {"key": "tag", "pattern": "^[A-Z]{2}$"},
+        {"key": "rem"}
+    ]}"#).unwrap();
+
+    let raw = |record: &str| super::parse_passport(record).raw;
+
+    assert!(policy.check(&raw("age:40 len:2m\nrem:anything")).is_empty());
+
+    let rules: Vec<Rule> = policy.check(&raw("len:7km tag:abc eye:blu tag:AB junk tag:AB eye:blu"))
+        .into_iter()
+        .map(|violation| violation.rule)
+        .collect();
+
+    assert_eq!(
+        vec![
+            Rule::Missing,
+            Rule::BadFormat("a number followed by m or mm".to_string()),
+            Rule::UnknownKey,
+            Rule::ConflictingValues(vec!["abc".to_string(), "AB".to_string(), "AB".to_string()]),
+            Rule::DuplicateKey,
+            Rule::Malformed,
+        ],
+        rules
+    );
+
+    policy.reject_unknown_keys = false;
+    policy.reject_repeated_keys = false;
+    let rules: Vec<Rule> = policy.check(&raw("age:40 len:7km tag:abc eye:blu tag:AB junk tag:AB eye:blu"))
+        .into_iter()
+        .map(|violation| violation.rule)
+        .collect();
+
+    assert_eq!(vec![Rule::BadFormat("a number followed by m or mm".to_string()), Rule::Malformed], rules);
+
+    assert_eq!(Err(Rule::BadFormat("a match for ^[A-Z]{2}$".to_string())), policy.fields[2].check.check("abc"));
+    assert_eq!(
+        Err(Rule::OutOfRange { min: 1, max: 999, unit: "mm".to_string() }),
+        policy.fields[1].check.check("1000mm")
+    );
+    assert_eq!(Err(Rule::BadFormat("4 digits".to_string())), DEFAULT_POLICY.fields[0].check.check("02000"));
+}
+
+#[test]
+fn test_policy_errors() {
+    let invalid = |input: &str| match Policy::from_json(input) {
+        Err(Error::Invalid { path, reason }) => (path, reason),
+        other => panic!("Expected invalid field, got {:?}", other),
+    };
+
+    // Other fields mentioning `"key"` don't throw the location off.
+    let (path, reason) = invalid(r#"{"fields": [{"key": "a", "one_of": ["\"key\""]}, {"key": "b", "range": [1, 2], "one_of": []}]}"#);
+    assert_eq!("fields[1]", path);
+    assert!(reason.starts_with("b has more than one of"));
+
+    let (path, reason) = invalid("{\"fields\": [\n  {\"key\": \"a\", \"pattern\": \"(\"}\n]}");
+    assert_eq!("fields[0]", path);
+    assert!(reason.starts_with("a has an invalid pattern"));
+
+    assert_eq!(("fields[1]".to_string(), "a is listed twice".to_string()), invalid(r#"{"fields": [{"key": "a"}, {"key": "a"}]}"#));
+    assert_eq!(
+        ("fields[0]".to_string(), "a has range going down from 5 to 1".to_string()),
+        invalid(r#"{"fields": [{"key": "a", "range": [5, 1]}]}"#)
+    );
+    assert_eq!(
+        ("fields[1]".to_string(), "b has the in range going down from 76 to 59".to_string()),
+        invalid(r#"{"fields": [{"key": "a"}, {"key": "b", "units": {"cm": [1, 2], "in": [76, 59]}}]}"#)
+    );
+
+    match Policy::from_json("{\"fields\": [\n  {\"key\": \"a\", \"maximum\": 3}\n]}") {
+        Err(Error::Parse { line, .. }) => assert_eq!(2, line),
+        other => panic!("Expected parse error, got {:?}", other),
+    }
+}
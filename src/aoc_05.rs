@@ -1,3 +1,9 @@
+use std::io::BufRead;
+
+use crate::error::Error;
+use crate::numbered_lines;
+use crate::solution::Solution;
+
 #[derive(Clone, PartialEq, Eq, Debug)]
 struct SeatRange {
     lower: u32,
@@ -42,23 +48,31 @@ impl Seat {
         Seat { row, col }
     }
 
-    pub fn from_ticket(ticket: &str) -> Seat {
+    pub fn from_ticket(ticket: &str) -> Result<Seat, Error> {
         let mut rows = SeatRange { lower: 0, upper: 127 };
         let mut cols = SeatRange { lower: 0, upper: 7 };
 
-        ticket.chars().for_each(|c| {
+        for (idx, c) in ticket.chars().enumerate() {
+            let range = match c {
+                'F' | 'B' => &mut rows,
+                'L' | 'R' => &mut cols,
+                _ => return Err(Error::parse(idx + 1, ticket, format!("unexpected character: {:?}", c))),
+            };
+
+            if range.resolve().is_some() {
+                return Err(Error::parse(idx + 1, ticket, format!("seat already resolved before {:?}", c)));
+            }
+
             match c {
-                'F' => rows.keep_lower_half(),
-                'B' => rows.keep_upper_half(),
-                'R' => cols.keep_upper_half(),
-                'L' => cols.keep_lower_half(),
-                _ => panic!("Unexpected input character: {}", c),
+                'F' | 'L' => range.keep_lower_half(),
+                _ => range.keep_upper_half(),
             }
-        });
+        }
 
-        Seat {
-            row: rows.resolve().expect("Rows not sufficiently resolved."),
-            col: cols.resolve().expect("Cols not sufficiently resolved."),
+        match (rows.resolve(), cols.resolve()) {
+            (Some(row), Some(col)) => Ok(Seat { row, col }),
+            (None, _) => Err(Error::parse(ticket.len() + 1, ticket, "rows not sufficiently resolved")),
+            (_, None) => Err(Error::parse(ticket.len() + 1, ticket, "cols not sufficiently resolved")),
         }
     }
 
@@ -67,6 +81,43 @@ impl Seat {
     }
 }
 
+fn parse_seat_ids(input: &mut dyn BufRead) -> Result<Vec<u32>, Error> {
+    numbered_lines(input).map(|line| {
+        let (line_no, text) = line?;
+
+        Seat::from_ticket(text.trim_end())
+            .map(|seat| seat.id())
+            .map_err(|e| e.on_line(line_no))
+    }).collect()
+}
+
+// Our seat is the only one missing from the list whose neighbours are both
+// present.
+pub fn find_missing_id(ids: &[u32]) -> Option<u32> {
+    let mut ids = ids.to_vec();
+    ids.sort_unstable();
+
+    ids.windows(2)
+        .find(|pair| pair[1] - pair[0] == 2)
+        .map(|pair| pair[0] + 1)
+}
+
+pub struct Day05;
+
+impl Solution for Day05 {
+    fn part_a(&self, input: &mut dyn BufRead) -> Result<String, Error> {
+        parse_seat_ids(input)?.iter().max()
+            .map(|id| id.to_string())
+            .ok_or_else(|| Error::NoAnswer("no seats in input".to_string()))
+    }
+
+    fn part_b(&self, input: &mut dyn BufRead) -> Result<String, Error> {
+        find_missing_id(&parse_seat_ids(input)?)
+            .map(|id| id.to_string())
+            .ok_or_else(|| Error::NoAnswer("no missing seat found".to_string()))
+    }
+}
+
 #[test]
 fn test_id() {
     assert_eq!(Seat::at(44, 5).id(), 357);
@@ -77,8 +128,25 @@ fn test_id() {
 
 #[test]
 fn test_from_ticket() {
-    assert_eq!(Seat::from_ticket("FBFBBFFRLR"), Seat { row: 44, col: 5 });
-    assert_eq!(Seat::from_ticket("BFFFBBFRRR"), Seat { row: 70, col: 7 });
-    assert_eq!(Seat::from_ticket("FFFBBBFRRR"), Seat { row: 14, col: 7 });
-    assert_eq!(Seat::from_ticket("BBFFBBFRLL"), Seat { row: 102, col: 4 });
+    assert_eq!(Seat::from_ticket("FBFBBFFRLR").unwrap(), Seat { row: 44, col: 5 });
+    assert_eq!(Seat::from_ticket("BFFFBBFRRR").unwrap(), Seat { row: 70, col: 7 });
+    assert_eq!(Seat::from_ticket("FFFBBBFRRR").unwrap(), Seat { row: 14, col: 7 });
+    assert_eq!(Seat::from_ticket("BBFFBBFRLL").unwrap(), Seat { row: 102, col: 4 });
+}
+
+#[test]
+fn test_from_ticket_errors() {
+    match Seat::from_ticket("FBFXBFFRLR") {
+        Err(Error::Parse { column, .. }) => assert_eq!(4, column),
+        other => panic!("Expected parse error, got {:?}", other),
+    }
+
+    assert!(Seat::from_ticket("FBFBBFFRL").is_err());
+    assert!(Seat::from_ticket("FBFBBFFFRLR").is_err());
+}
+
+#[test]
+fn test_find_missing_id() {
+    assert_eq!(Some(12), find_missing_id(&[14, 10, 11, 13, 15]));
+    assert_eq!(None, find_missing_id(&[3, 4, 5]));
 }
@@ -1,7 +1,12 @@
 use std::collections::HashSet;
+use std::io::BufRead;
 
 use itertools::Itertools;
 
+use crate::RecordReader;
+use crate::error::Error;
+use crate::solution::Solution;
+
 pub fn unique_chars(input: &str) -> Vec<char> {
     input.chars().unique().filter(|c| *c != '\n').collect()
 }
@@ -9,13 +14,40 @@ pub fn unique_chars(input: &str) -> Vec<char> {
 pub fn all_answered_yes(input: &str) -> HashSet<char> {
     let mut line_reader = input.lines();
 
-    let init: HashSet<char> = line_reader.next().expect("Must have at least one line!").chars().collect();
+    let init: HashSet<char> = match line_reader.next() {
+        Some(line) => line.chars().collect(),
+        None => return HashSet::new(),
+    };
 
     line_reader
-        .filter(|line| *line != "")
+        .filter(|line| !line.is_empty())
         .fold(init, |a, e| a.intersection(&e.chars().collect()).copied().collect())
 }
 
+pub struct Day06;
+
+impl Solution for Day06 {
+    fn part_a(&self, input: &mut dyn BufRead) -> Result<String, Error> {
+        let mut result = 0;
+
+        for record in RecordReader::new(input) {
+            result += unique_chars(record?.as_str()).len();
+        }
+
+        Ok(result.to_string())
+    }
+
+    fn part_b(&self, input: &mut dyn BufRead) -> Result<String, Error> {
+        let mut result = 0;
+
+        for record in RecordReader::new(input) {
+            result += all_answered_yes(record?.as_str()).len();
+        }
+
+        Ok(result.to_string())
+    }
+}
+
 #[test]
 fn test_unique_chars() {
     assert_eq!(vec!['a', 'b', 'c', 'x', 'y', 'z'], unique_chars("abcx\nabcy\nabcz\n"));
@@ -27,5 +59,6 @@ fn test_all_answered_yes() {
     assert_eq!(hashset![], all_answered_yes("a\nb\nc\n"));
     assert_eq!(hashset!['a'], all_answered_yes("ab\nac\n"));
     assert_eq!(hashset!['a'], all_answered_yes("a\na\na\na\n"));
+    assert_eq!(hashset![], all_answered_yes(""));
     assert_eq!(hashset!['m', 'r', 'c', 'q', 'l', 'o'], all_answered_yes("rmcqdblnto\nqlcnmor\nrhmzalcsoq\nlgxcrmnqovd\n\n"));
 }
\ No newline at end of file
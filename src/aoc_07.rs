@@ -1,132 +1,1187 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
 use std::io::BufRead;
+use std::rc::Rc;
+use std::sync::Arc;
 
 use lazy_static::lazy_static;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::numbered_lines;
+use crate::solution::Solution;
+
+pub mod generate;
 
 type BagIndex = usize;
 
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct BagRule {
     desc: String,
-    can_hold: HashMap<String, u8>,
+    // In the order the rule lists them.
+    can_hold: Vec<(String, u64)>,
 }
 
-#[derive(Debug)]
-struct BagGraphNode {}
+#[derive(Debug, Default)]
+struct BagGraphNode {
+    // Lines with a rule for this bag; empty if it is only ever held.
+    defined_on: Vec<usize>,
+}
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 struct BagGraphEdge {
     idx: BagIndex,
-    count: u8,
+    count: u64,
 }
 
+// Bag names, each allocated once and numbered in order of first use.
 #[derive(Debug, Default)]
+struct Interner {
+    ids: HashMap<Arc<str>, BagIndex>,
+    names: Vec<Arc<str>>,
+}
+
+impl Interner {
+    fn intern(&mut self, name: &str) -> BagIndex {
+        if let Some(idx) = self.ids.get(name) {
+            return *idx;
+        }
+
+        let name: Arc<str> = Arc::from(name);
+        let idx = self.names.len();
+
+        self.names.push(name.clone());
+        self.ids.insert(name, idx);
+        idx
+    }
+
+    fn get(&self, name: &str) -> Option<BagIndex> {
+        self.ids.get(name).copied()
+    }
+
+    fn name(&self, idx: BagIndex) -> &str {
+        &self.names[idx]
+    }
+}
+
+// Compressed sparse rows: the items for bag `idx` are
+// `items[offsets[idx]..offsets[idx + 1]]`.
+#[derive(Debug, Default)]
+struct Adjacency<T> {
+    offsets: Vec<usize>,
+    items: Vec<T>,
+}
+
+impl<T> Adjacency<T> {
+    // Groups `(bag, item)` pairs by bag, keeping each bag's items in the
+    // order they were given.
+    fn build(bag_count: usize, mut pairs: Vec<(BagIndex, T)>) -> Adjacency<T> {
+        pairs.sort_by_key(|(idx, _)| *idx);
+
+        let mut offsets = vec![0; bag_count + 1];
+        for (idx, _) in &pairs {
+            offsets[idx + 1] += 1;
+        }
+        for idx in 0..bag_count {
+            offsets[idx + 1] += offsets[idx];
+        }
+
+        Adjacency { offsets, items: pairs.into_iter().map(|(_, item)| item).collect() }
+    }
+
+    fn get(&self, idx: BagIndex) -> &[T] {
+        &self.items[self.offsets[idx]..self.offsets[idx + 1]]
+    }
+}
+
+// Collects rules, then lays them out as a `BagGraph`. Bags are numbered in
+// the order the rules first mention them, and each bag's edges kept in clause
+// order, so the same rules always give the same indices and traversal order.
+#[derive(Default)]
+struct BagGraphBuilder {
+    names: Interner,
+    bags: Vec<BagGraphNode>,
+    edges: Vec<(BagIndex, BagGraphEdge)>,
+}
+
+impl BagGraphBuilder {
+    fn intern(&mut self, bag_desc: &str) -> BagIndex {
+        let idx = self.names.intern(bag_desc);
+        if idx == self.bags.len() {
+            self.bags.push(BagGraphNode::default());
+        }
+
+        idx
+    }
+
+    fn define(&mut self, bag_desc: &str, line_no: usize) -> BagIndex {
+        let idx = self.intern(bag_desc);
+        self.bags[idx].defined_on.push(line_no);
+        idx
+    }
+
+    fn link(&mut self, source: BagIndex, holds: &str, count: u64) {
+        let idx = self.intern(holds);
+        self.edges.push((source, BagGraphEdge { idx, count }));
+    }
+
+    fn build(self) -> BagGraph {
+        let bag_count = self.bags.len();
+        let held_by = self.edges.iter().map(|(source, edge)| (edge.idx, *source)).collect();
+
+        let mut graph = BagGraph {
+            names: self.names,
+            bags: self.bags,
+            links: Adjacency::build(bag_count, self.edges),
+            held_by: Adjacency::build(bag_count, held_by),
+            totals: vec![],
+        };
+
+        graph.totals = graph.inner_totals();
+        graph
+    }
+}
+
+// Bags which eventually hold themselves, in order, with the first bag
+// repeated at the end.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Cycle(pub Vec<String>);
+
+impl fmt::Display for Cycle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bags hold each other: {}", self.0.join(" -> "))
+    }
+}
+
+impl std::error::Error for Cycle {}
+
+// A query named a bag with no node in the graph. `suggestions` holds the
+// closest known names, nearest first.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct UnknownBag {
+    pub name: String,
+    pub suggestions: Vec<String>,
+}
+
+impl fmt::Display for UnknownBag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown bag: {}", self.name)?;
+
+        if !self.suggestions.is_empty() {
+            write!(f, " (did you mean {}?)", self.suggestions.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for UnknownBag {}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum QueryError {
+    UnknownBag(UnknownBag),
+    Cycle(Cycle),
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryError::UnknownBag(unknown) => write!(f, "{}", unknown),
+            QueryError::Cycle(cycle) => write!(f, "{}", cycle),
+        }
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+impl From<UnknownBag> for QueryError {
+    fn from(unknown: UnknownBag) -> QueryError {
+        QueryError::UnknownBag(unknown)
+    }
+}
+
+impl From<Cycle> for QueryError {
+    fn from(cycle: Cycle) -> QueryError {
+        QueryError::Cycle(cycle)
+    }
+}
+
+// How many bags are inside a bag, if they can be counted.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum BagTotal {
+    Count(u64),
+    // More than fit in a u64.
+    Overflow,
+    // The bag eventually holds a bag which holds itself, so there is no end
+    // to them.
+    Cyclic,
+}
+
+impl fmt::Display for BagTotal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BagTotal::Count(count) => write!(f, "{}", count),
+            BagTotal::Overflow => write!(f, "more than {}", u64::MAX),
+            BagTotal::Cyclic => write!(f, "endless"),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum CountError {
+    UnknownBag(UnknownBag),
+    Cycle(Cycle),
+    // The number of bags inside `bag` doesn't fit in a u64.
+    Overflow { bag: String },
+}
+
+impl fmt::Display for CountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CountError::UnknownBag(unknown) => write!(f, "{}", unknown),
+            CountError::Cycle(cycle) => write!(f, "{}", cycle),
+            CountError::Overflow { bag } => write!(f, "{} bags hold more than {} bags", bag, u64::MAX),
+        }
+    }
+}
+
+impl std::error::Error for CountError {}
+
+impl From<UnknownBag> for CountError {
+    fn from(unknown: UnknownBag) -> CountError {
+        CountError::UnknownBag(unknown)
+    }
+}
+
+impl From<Cycle> for CountError {
+    fn from(cycle: Cycle) -> CountError {
+        CountError::Cycle(cycle)
+    }
+}
+
+// Levenshtein distance, counting characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + if a_char == *b_char { 0 } else { 1 };
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+// How many suggestions an `UnknownBag` carries at most.
+const MAX_SUGGESTIONS: usize = 3;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Problem {
+    Cycle(Cycle),
+    // A bag held by another, but with no rule of its own.
+    Undefined(String),
+    // A bag with rules on more than one line.
+    Duplicate { bag: String, lines: Vec<usize> },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::Cycle(cycle) => write!(f, "{}", cycle),
+            Problem::Undefined(bag) => write!(f, "no rule for {} bags", bag),
+            Problem::Duplicate { bag, lines } => {
+                let lines: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+                write!(f, "{} bags have rules on lines {}", bag, lines.join(", "))
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Visit {
+    New,
+    OnPath,
+    Done,
+}
+
+// Which bags `to_dot` highlights.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Highlight<'a> {
+    // The bag and everything it eventually holds.
+    From(&'a str),
+    // The bag and everything eventually holding it.
+    To(&'a str),
+}
+
+// A rule as written by `to_json`.
+#[derive(Serialize, Deserialize, Debug)]
+struct JsonRule {
+    bag: String,
+    contains: Vec<JsonContents>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct JsonContents {
+    count: u64,
+    bag: String,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Direction {
+    // Towards the bags a bag holds.
+    Held,
+    // Towards the bags holding a bag.
+    Holders,
+}
+
+// Every bag reachable from a starting bag in one direction, each yielded
+// once. The starting bag is only included if it is part of a cycle.
+pub struct Reachable<'a> {
+    graph: &'a BagGraph,
+    direction: Direction,
+    seen: Vec<bool>,
+    frontier: Vec<BagIndex>,
+}
+
+impl<'a> Reachable<'a> {
+    fn new(graph: &'a BagGraph, start: BagIndex, direction: Direction) -> Reachable<'a> {
+        Reachable::from_all(graph, Some(start), direction)
+    }
+
+    // Every bag reachable from any of `starts`, each still yielded once.
+    fn from_all(graph: &'a BagGraph, starts: impl IntoIterator<Item=BagIndex>, direction: Direction) -> Reachable<'a> {
+        let mut reachable = Reachable {
+            graph,
+            direction,
+            seen: vec![false; graph.bags.len()],
+            frontier: vec![],
+        };
+
+        for start in starts {
+            reachable.visit_neighbours(start);
+        }
+
+        reachable
+    }
+
+    fn visit_neighbours(&mut self, idx: BagIndex) {
+        let graph = self.graph;
+
+        match self.direction {
+            Direction::Held => graph.edges(idx).iter().for_each(|edge| self.visit(edge.idx)),
+            Direction::Holders => graph.holders(idx).iter().for_each(|holder| self.visit(*holder)),
+        }
+    }
+
+    fn visit(&mut self, idx: BagIndex) {
+        if !self.seen[idx] {
+            self.seen[idx] = true;
+            self.frontier.push(idx);
+        }
+    }
+}
+
+impl<'a> Iterator for Reachable<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let idx = self.frontier.pop()?;
+        self.visit_neighbours(idx);
+
+        Some(self.graph.name(idx))
+    }
+}
+
+// Everything inside a bag, as `BagGraph::packing_tree` expands it.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PackingTree {
+    pub bag: String,
+    // How many of this bag its parent holds; 1 for the outermost bag.
+    pub count: u64,
+    // How many of this bag there are in the whole packing.
+    pub total: u64,
+    // The number of bags inside one of this bag.
+    pub subtotal: u64,
+    // Sorted by bag name.
+    pub contents: Vec<PackingTree>,
+}
+
+impl PackingTree {
+    // The number of levels of bags inside this one: 0 for an empty bag.
+    pub fn depth(&self) -> usize {
+        let mut deepest = 0;
+        let mut stack = vec![(self, 0)];
+
+        while let Some((tree, level)) = stack.pop() {
+            deepest = deepest.max(level);
+            stack.extend(tree.contents.iter().map(|held| (held, level + 1)));
+        }
+
+        deepest
+    }
+}
+
+// Like every traversal of a tree, dropping one goes level by level, so deep
+// trees don't overflow the stack.
+impl Drop for PackingTree {
+    fn drop(&mut self) {
+        let mut stack = std::mem::take(&mut self.contents);
+
+        while let Some(mut tree) = stack.pop() {
+            stack.append(&mut tree.contents);
+        }
+    }
+}
+
+// One line per bag, indented under the bag holding it:
+//
+//     1 shiny gold (1 in total): 32 inside each
+//       1 dark olive (1 in total): 7 inside each
+//         3 faded blue (3 in total)
+impl fmt::Display for PackingTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut stack = vec![(self, 0)];
+
+        while let Some((tree, indent)) = stack.pop() {
+            write!(f, "{:indent$}{} {} ({} in total)", "", tree.count, tree.bag, tree.total, indent = indent)?;
+            if tree.subtotal > 0 {
+                write!(f, ": {} inside each", tree.subtotal)?;
+            }
+            writeln!(f)?;
+
+            stack.extend(tree.contents.iter().rev().map(|held| (held, indent + 2)));
+        }
+
+        Ok(())
+    }
+}
+
+// A change in how many of one bag another holds. A count of None means the
+// bag isn't held at all on that side.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct EdgeChange {
+    pub holder: String,
+    pub held: String,
+    pub before: Option<u64>,
+    pub after: Option<u64>,
+}
+
+// A change in `inner_bag_count`, for a bag on both sides.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct CountChange {
+    pub bag: String,
+    pub before: BagTotal,
+    pub after: BagTotal,
+}
+
+// A change in `could_hold`. A bag on only one side gains or loses all of its
+// holders.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct HoldersChange {
+    pub bag: String,
+    pub gained: Vec<String>,
+    pub lost: Vec<String>,
+}
+
+// What `BagGraph::diff` found, with every list sorted by bag name.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct BagDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub edges: Vec<EdgeChange>,
+    pub counts: Vec<CountChange>,
+    pub holders: Vec<HoldersChange>,
+}
+
+impl BagDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.edges.is_empty()
+            && self.counts.is_empty() && self.holders.is_empty()
+    }
+}
+
+// One change per line.
+impl fmt::Display for BagDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for bag in &self.added {
+            writeln!(f, "added bag: {}", bag)?;
+        }
+
+        for bag in &self.removed {
+            writeln!(f, "removed bag: {}", bag)?;
+        }
+
+        for change in &self.edges {
+            match (change.before, change.after) {
+                (Some(before), Some(after)) =>
+                    writeln!(f, "{} holds {} {} (was {})", change.holder, after, change.held, before)?,
+                (None, Some(after)) => writeln!(f, "{} now holds {} {}", change.holder, after, change.held)?,
+                _ => writeln!(f, "{} no longer holds {}", change.holder, change.held)?,
+            }
+        }
+
+        for change in &self.counts {
+            writeln!(f, "{} holds {} bags (was {})", change.bag, change.after, change.before)?;
+        }
+
+        for change in &self.holders {
+            if !change.gained.is_empty() {
+                writeln!(f, "{} can now be held by {}", change.bag, change.gained.join(", "))?;
+            }
+            if !change.lost.is_empty() {
+                writeln!(f, "{} can no longer be held by {}", change.bag, change.lost.join(", "))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Bags are numbered by `names`, which indexes `bags` and both edge lists.
+#[derive(Debug)]
 pub struct BagGraph {
-    // Allow name lookup for linking held bags.
-    names: HashMap<String, BagIndex>,
+    names: Interner,
     bags: Vec<BagGraphNode>,
-    links: HashMap<BagIndex, Vec<BagGraphEdge>>,
+    links: Adjacency<BagGraphEdge>,
+    // The reverse of `links`: the bags holding each bag.
+    held_by: Adjacency<BagIndex>,
+    // The number of bags inside each bag, worked out once when the graph is
+    // built so that counting queries only look it up.
+    totals: Vec<BagTotal>,
 }
 
 impl BagGraph {
-    fn link(&mut self, bag_desc: &String, holds: &String, count: u8) {
-        let source_bag_id = self.find_or_create_idx(bag_desc);
-        let held_bag_id = self.find_or_create_idx(holds);
+    fn name(&self, idx: BagIndex) -> &str {
+        self.names.name(idx)
+    }
+
+    fn edges(&self, idx: BagIndex) -> &[BagGraphEdge] {
+        self.links.get(idx)
+    }
+
+    fn holders(&self, idx: BagIndex) -> &[BagIndex] {
+        self.held_by.get(idx)
+    }
+
+    fn lookup(&self, bag_desc: &str) -> Result<BagIndex, UnknownBag> {
+        if let Some(idx) = self.names.get(bag_desc) {
+            return Ok(idx);
+        }
+
+        // Anything further away than a third of the name is more likely a
+        // different bag than a typo.
+        let limit = (bag_desc.chars().count() / 3).max(1);
+
+        let mut close: Vec<(usize, &str)> = (0..self.bags.len())
+            .map(|idx| (edit_distance(bag_desc, self.name(idx)), self.name(idx)))
+            .filter(|(distance, _)| *distance <= limit)
+            .collect();
+        close.sort_unstable();
+
+        Err(UnknownBag {
+            name: bag_desc.to_string(),
+            suggestions: close.into_iter().take(MAX_SUGGESTIONS).map(|(_, name)| name.to_string()).collect(),
+        })
+    }
+
+    // Every bag which eventually holds `bag_desc`.
+    pub fn ancestors(&self, bag_desc: &str) -> Result<Reachable<'_>, UnknownBag> {
+        Ok(Reachable::new(self, self.lookup(bag_desc)?, Direction::Holders))
+    }
+
+    // Every bag `bag_desc` eventually holds.
+    pub fn descendants(&self, bag_desc: &str) -> Result<Reachable<'_>, UnknownBag> {
+        Ok(Reachable::new(self, self.lookup(bag_desc)?, Direction::Held))
+    }
+
+    // Bags which no other bag holds.
+    pub fn roots(&self) -> impl Iterator<Item=&str> + '_ {
+        (0..self.bags.len())
+            .filter(move |idx| self.holders(*idx).is_empty())
+            .map(move |idx| self.name(idx))
+    }
+
+    // Bags which hold nothing.
+    pub fn leaves(&self) -> impl Iterator<Item=&str> + '_ {
+        (0..self.bags.len())
+            .filter(move |idx| self.edges(*idx).is_empty())
+            .map(move |idx| self.name(idx))
+    }
+
+    // The `n`th bag next to `idx` in a direction.
+    fn neighbour(&self, idx: BagIndex, direction: Direction, n: usize) -> Option<BagIndex> {
+        match direction {
+            Direction::Held => self.edges(idx).get(n).map(|edge| edge.idx),
+            Direction::Holders => self.holders(idx).get(n).copied(),
+        }
+    }
+
+    // Every cycle found by a depth-first search from `starts` in a direction,
+    // one per edge leading back into the current path. Bags are listed in the
+    // order they hold each other either way.
+    fn find_cycles_from(&self, starts: impl IntoIterator<Item=BagIndex>, direction: Direction) -> Vec<Cycle> {
+        let mut state = vec![Visit::New; self.bags.len()];
+        let mut cycles = vec![];
+
+        for start in starts {
+            if state[start] != Visit::New {
+                continue;
+            }
+
+            // Each bag on the path, with the index of its next edge to follow.
+            let mut path: Vec<(BagIndex, usize)> = vec![(start, 0)];
+            state[start] = Visit::OnPath;
+
+            while let Some(&(idx, next_edge)) = path.last() {
+                match self.neighbour(idx, direction, next_edge) {
+                    Some(next) => {
+                        path.last_mut().unwrap().1 += 1;
+
+                        match state[next] {
+                            Visit::New => {
+                                state[next] = Visit::OnPath;
+                                path.push((next, 0));
+                            }
+                            Visit::OnPath => {
+                                let first = path.iter().position(|(on_path, _)| *on_path == next).unwrap();
+                                let mut names: Vec<String> = path[first..].iter()
+                                    .map(|(on_path, _)| self.name(*on_path).to_string())
+                                    .collect();
+                                names.push(self.name(next).to_string());
+
+                                if direction == Direction::Holders {
+                                    names.reverse();
+                                }
+
+                                cycles.push(Cycle(names));
+                            }
+                            Visit::Done => {}
+                        }
+                    }
+                    None => {
+                        state[idx] = Visit::Done;
+                        path.pop();
+                    }
+                }
+            }
+        }
+
+        cycles
+    }
+
+    fn find_cycles(&self) -> Vec<Cycle> {
+        self.find_cycles_from(0..self.bags.len(), Direction::Held)
+    }
+
+    // Fails on a cycle reachable from `idx` in a direction, ignoring any
+    // elsewhere in the graph.
+    fn check_acyclic_from(&self, idx: BagIndex, direction: Direction) -> Result<(), Cycle> {
+        match self.find_cycles_from(Some(idx), direction).into_iter().next() {
+            Some(cycle) => Err(cycle),
+            None => Ok(()),
+        }
+    }
+
+    // Everything wrong with the rule set; empty if it is valid.
+    pub fn validate(&self) -> Vec<Problem> {
+        let mut problems: Vec<Problem> = self.find_cycles().into_iter().map(Problem::Cycle).collect();
 
-        let new_edge = BagGraphEdge { idx: held_bag_id, count: count };
+        for (idx, bag) in self.bags.iter().enumerate() {
+            if bag.defined_on.is_empty() {
+                problems.push(Problem::Undefined(self.name(idx).to_string()));
+            }
+        }
 
-        if !self.links.contains_key(&source_bag_id) {
-            self.links.insert(source_bag_id, vec![new_edge]);
-        } else {
-            self.links.get_mut(&source_bag_id).unwrap().push(new_edge);
+        for (idx, bag) in self.bags.iter().enumerate() {
+            if bag.defined_on.len() > 1 {
+                problems.push(Problem::Duplicate { bag: self.name(idx).to_string(), lines: bag.defined_on.clone() });
+            }
         }
+
+        problems
     }
 
-    pub fn can_eventually_hold(&mut self, bag_desc: &str, target: &str) -> bool {
-        let needle = self.find_or_create_idx(&target.to_string());
-        let haystack = self.find_or_create_idx(&bag_desc.to_string());
+    pub fn can_eventually_hold(&self, bag_desc: &str, target: &str) -> Result<bool, QueryError> {
+        let haystack = self.lookup(bag_desc)?;
+        let needle = self.lookup(target)?;
 
-        self.can_eventually_hold_by_idx(needle, haystack)
+        self.check_acyclic_from(haystack, Direction::Held)?;
+
+        Ok(self.can_eventually_hold_by_idx(needle, haystack))
     }
 
     fn can_eventually_hold_by_idx(&self, needle: usize, haystack: usize) -> bool {
-        let mut searched: Vec<usize> = vec![];
+        let mut searched = vec![false; self.bags.len()];
         let mut frontier = vec![haystack];
 
         while let Some(bag_id) = frontier.pop() {
-            searched.push(bag_id);
+            if searched[bag_id] {
+                continue;
+            }
+            searched[bag_id] = true;
 
-            if let Some(targets) = self.links.get(&bag_id) {
-                for target in targets {
-                    if target.idx == needle {
-                        return true;
-                    }
+            for target in self.edges(bag_id) {
+                if target.idx == needle {
+                    return true;
+                }
 
-                    if !frontier.contains(&target.idx) {
-                        frontier.push(target.idx);
+                frontier.push(target.idx);
+            }
+        }
+
+        false
+    }
+
+    pub fn could_hold(&self, bag_desc: &str) -> Result<Vec<String>, QueryError> {
+        let idx = self.lookup(bag_desc)?;
+
+        self.check_acyclic_from(idx, Direction::Holders)?;
+
+        Ok(Reachable::new(self, idx, Direction::Holders).map(str::to_string).collect())
+    }
+
+    // The number of bags inside each bag, indexed by bag. Each bag is only
+    // evaluated once, after everything it holds, and an edge back into the
+    // current path makes every bag on the way to it `Cyclic`.
+    fn inner_totals(&self) -> Vec<BagTotal> {
+        let mut state = vec![Visit::New; self.bags.len()];
+        let mut totals = vec![BagTotal::Count(0); self.bags.len()];
+
+        for start in 0..self.bags.len() {
+            if state[start] != Visit::New {
+                continue;
+            }
+
+            let mut path: Vec<(BagIndex, usize)> = vec![(start, 0)];
+            state[start] = Visit::OnPath;
+
+            while let Some(&(idx, next_edge)) = path.last() {
+                match self.edges(idx).get(next_edge) {
+                    Some(edge) => {
+                        path.last_mut().unwrap().1 += 1;
+
+                        if state[edge.idx] == Visit::New {
+                            state[edge.idx] = Visit::OnPath;
+                            path.push((edge.idx, 0));
+                        }
+                    }
+                    None => {
+                        totals[idx] = self.inner_total(idx, &state, &totals);
+                        state[idx] = Visit::Done;
+                        path.pop();
                     }
                 }
             }
         }
 
-        return false;
+        totals
     }
 
-    pub fn could_hold(&self, bag_desc: &str) -> Vec<String> {
-        let mut result = vec![];
-        let needle = self.names.get(bag_desc).unwrap();
+    // The total for `idx`, once everything it holds is done or on the path.
+    fn inner_total(&self, idx: BagIndex, state: &[Visit], totals: &[BagTotal]) -> BagTotal {
+        let mut sum = Some(0u64);
 
-        for (name, idx) in &self.names {
-            if self.can_eventually_hold_by_idx(*needle, *idx) {
-                result.push(name.clone());
+        for edge in self.edges(idx) {
+            match (state[edge.idx], totals[edge.idx]) {
+                (Visit::OnPath, _) | (_, BagTotal::Cyclic) => return BagTotal::Cyclic,
+                (_, BagTotal::Overflow) => sum = None,
+                (_, BagTotal::Count(held)) => {
+                    sum = sum.and_then(|sum| sum.checked_add(held.checked_add(1)?.checked_mul(edge.count)?));
+                }
             }
         }
 
-        result
+        sum.map_or(BagTotal::Overflow, BagTotal::Count)
     }
 
-    pub fn inner_bag_count(&self, bag_desc: &str) -> usize {
-        let idx = self.names.get(bag_desc).unwrap();
+    // Turns a bag's total into a count, or the reason it has none.
+    fn count_of(&self, idx: BagIndex, total: BagTotal) -> Result<u64, CountError> {
+        match total {
+            BagTotal::Count(count) => Ok(count),
+            BagTotal::Overflow => Err(CountError::Overflow { bag: self.name(idx).to_string() }),
+            BagTotal::Cyclic => {
+                let cycle = self.find_cycles_from(Some(idx), Direction::Held).into_iter().next();
+                Err(CountError::Cycle(cycle.expect("a cyclic bag reaches a cycle")))
+            }
+        }
+    }
+
+    // Fails only on a cycle among the bags inside `bag_desc`.
+    pub fn inner_bag_count(&self, bag_desc: &str) -> Result<u64, CountError> {
+        let idx = self.lookup(bag_desc)?;
 
-        self.inner_bag_count_idx(*idx)
+        self.count_of(idx, self.totals[idx])
     }
 
-    fn inner_bag_count_idx(&self, idx: BagIndex) -> usize {
-        match self.links.get(&idx) {
-            None => 0, // The bag cannot hold anything.
-            Some(target_bags) =>
-                target_bags
-                    .iter()
-                    .map(|t| ((self.inner_bag_count_idx(t.idx) + 1) * t.count as usize))
-                    .sum()
+    // The number of bags inside every bag.
+    pub fn inner_bag_counts(&self) -> HashMap<String, BagTotal> {
+        self.totals.iter()
+            .copied()
+            .enumerate()
+            .map(|(idx, total)| (self.name(idx).to_string(), total))
+            .collect()
+    }
+
+    // Every bag inside `bag_desc`, expanded level by level. Bags held in
+    // several places appear under each of them.
+    pub fn packing_tree(&self, bag_desc: &str) -> Result<PackingTree, CountError> {
+        let idx = self.lookup(bag_desc)?;
+        let totals = &self.totals;
+
+        // Everything inside a bag which can be counted can be too.
+        self.count_of(idx, totals[idx])?;
+
+        let overflow = |idx: BagIndex| CountError::Overflow { bag: self.name(idx).to_string() };
+
+        // Each bag on the path down to the one being expanded, with its tree
+        // so far and the index of its next edge to follow.
+        let mut path: Vec<(BagIndex, PackingTree, usize)> = vec![(idx, self.packing_node(idx, 1, 1), 0)];
+
+        loop {
+            let (idx, tree, next_edge) = path.last_mut().unwrap();
+            let idx = *idx;
+
+            match self.edges(idx).get(*next_edge) {
+                Some(edge) => {
+                    *next_edge += 1;
+
+                    let held_total = tree.total.checked_mul(edge.count).ok_or_else(|| overflow(idx))?;
+                    path.push((edge.idx, self.packing_node(edge.idx, edge.count, held_total), 0));
+                }
+                None => {
+                    let (_, mut tree, _) = path.pop().unwrap();
+                    tree.subtotal = self.count_of(idx, totals[idx])?;
+                    tree.contents.sort_by(|a, b| a.bag.cmp(&b.bag));
+
+                    match path.last_mut() {
+                        Some((_, holder, _)) => holder.contents.push(tree),
+                        None => return Ok(tree),
+                    }
+                }
+            }
+        }
+    }
+
+    // A packing tree for one bag, before anything inside it is added.
+    fn packing_node(&self, idx: BagIndex, count: u64, total: u64) -> PackingTree {
+        PackingTree { bag: self.name(idx).to_string(), count, total, subtotal: 0, contents: vec![] }
+    }
+
+    // The bags `bag_desc` holds directly, with their counts. Empty for an
+    // unknown bag.
+    fn contents(&self, bag_desc: &str) -> BTreeMap<&str, u64> {
+        match self.names.get(bag_desc) {
+            Some(idx) => self.edges(idx).iter().map(|edge| (self.name(edge.idx), edge.count)).collect(),
+            None => BTreeMap::new(),
         }
     }
 
-    fn find_or_create_idx(&mut self, bag_desc: &String) -> BagIndex {
-        if !self.names.contains_key(bag_desc) {
-            let val = self.bags.len();
+    // Every bag eventually holding each of `starts` or any bag holding them,
+    // indexed by bag. Bags holding each other are found together, as strongly
+    // connected components in an iterative form of Tarjan's algorithm, and
+    // share one set built from the sets of the bags holding them, so each
+    // bag's holders are only looked at once.
+    fn holder_closure(&self, starts: impl IntoIterator<Item=BagIndex>) -> Vec<Option<Rc<BTreeSet<BagIndex>>>> {
+        const UNVISITED: usize = usize::MAX;
 
-            self.bags.push(BagGraphNode {});
+        let count = self.bags.len();
+        let mut index = vec![UNVISITED; count];
+        let mut low_link = vec![0; count];
+        let mut on_stack = vec![false; count];
+        let mut stack = vec![];
+        let mut next_index = 0;
+        let mut closure: Vec<Option<Rc<BTreeSet<BagIndex>>>> = vec![None; count];
+
+        for root in starts {
+            if index[root] != UNVISITED {
+                continue;
+            }
+
+            // Each bag being searched, with the index of its next holder.
+            let mut work: Vec<(BagIndex, usize)> = vec![(root, 0)];
+            index[root] = next_index;
+            low_link[root] = next_index;
+            next_index += 1;
+            stack.push(root);
+            on_stack[root] = true;
+
+            while let Some(&(idx, next_holder)) = work.last() {
+                if let Some(holder) = self.neighbour(idx, Direction::Holders, next_holder) {
+                    work.last_mut().unwrap().1 += 1;
+
+                    if index[holder] == UNVISITED {
+                        index[holder] = next_index;
+                        low_link[holder] = next_index;
+                        next_index += 1;
+                        stack.push(holder);
+                        on_stack[holder] = true;
+                        work.push((holder, 0));
+                    } else if on_stack[holder] {
+                        low_link[idx] = low_link[idx].min(index[holder]);
+                    }
+
+                    continue;
+                }
+
+                work.pop();
+
+                if let Some(&(parent, _)) = work.last() {
+                    low_link[parent] = low_link[parent].min(low_link[idx]);
+                }
+
+                if low_link[idx] == index[idx] {
+                    let mut component = vec![];
+
+                    loop {
+                        let member = stack.pop().unwrap();
+                        on_stack[member] = false;
+                        component.push(member);
+
+                        if member == idx {
+                            break;
+                        }
+                    }
 
-            self.names.insert(bag_desc.clone(), val);
+                    // Holders outside the component were all finished first.
+                    let mut holders = BTreeSet::new();
+                    for member in &component {
+                        for holder in self.holders(*member) {
+                            holders.insert(*holder);
+
+                            if let Some(outer) = &closure[*holder] {
+                                holders.extend(outer.iter().copied());
+                            }
+                        }
+                    }
+
+                    let holders = Rc::new(holders);
+                    for member in component {
+                        closure[member] = Some(holders.clone());
+                    }
+                }
+            }
+        }
+
+        closure
+    }
+
+    // The names of the bags in `closure` holding `bag_desc`. Empty for an
+    // unknown bag.
+    fn holder_names(&self, closure: &[Option<Rc<BTreeSet<BagIndex>>>], bag_desc: &str) -> BTreeSet<&str> {
+        match self.names.get(bag_desc).and_then(|idx| closure[idx].as_ref()) {
+            Some(holders) => holders.iter().map(|idx| self.name(*idx)).collect(),
+            None => BTreeSet::new(),
+        }
+    }
+
+    // How the rules in `other` differ from these. Bags holding a cycle on
+    // either side have an endless count there.
+    pub fn diff(&self, other: &BagGraph) -> BagDiff {
+        let names: BTreeSet<&str> = self.names.names.iter().chain(&other.names.names).map(|name| &**name).collect();
+
+        let mut diff = BagDiff::default();
+        // Bags whose holders may have changed, as an edge into them did.
+        let mut retargeted = vec![];
+
+        for name in names {
+            let before_idx = self.names.get(name);
+            let after_idx = other.names.get(name);
+
+            match (before_idx, after_idx) {
+                (Some(before), Some(after)) if self.totals[before] != other.totals[after] => {
+                    diff.counts.push(CountChange {
+                        bag: name.to_string(),
+                        before: self.totals[before],
+                        after: other.totals[after],
+                    });
+                }
+                (Some(_), None) => diff.removed.push(name.to_string()),
+                (None, Some(_)) => diff.added.push(name.to_string()),
+                _ => {}
+            }
+
+            let before = self.contents(name);
+            let after = other.contents(name);
+            let held: BTreeSet<&str> = before.keys().chain(after.keys()).copied().collect();
+
+            for held in held {
+                let (before, after) = (before.get(held).copied(), after.get(held).copied());
+
+                if before != after {
+                    diff.edges.push(EdgeChange { holder: name.to_string(), held: held.to_string(), before, after });
+                    retargeted.push(held);
+                }
+            }
+        }
+
+        // A new or removed edge into a bag can only change who holds that
+        // bag or the bags inside it.
+        let mut affected: BTreeSet<&str> = retargeted.iter().copied().collect();
+
+        for graph in &[self, other] {
+            let starts = retargeted.iter().filter_map(|bag| graph.names.get(bag));
+            affected.extend(Reachable::from_all(graph, starts, Direction::Held));
+        }
+
+        let indices = |graph: &BagGraph| -> Vec<BagIndex> {
+            affected.iter().filter_map(|bag| graph.names.get(bag)).collect()
+        };
+        let before_closure = self.holder_closure(indices(self));
+        let after_closure = other.holder_closure(indices(other));
+
+        for bag in &affected {
+            let before = self.holder_names(&before_closure, bag);
+            let after = other.holder_names(&after_closure, bag);
+
+            let gained: Vec<String> = after.difference(&before).map(|name| name.to_string()).collect();
+            let lost: Vec<String> = before.difference(&after).map(|name| name.to_string()).collect();
+
+            if !gained.is_empty() || !lost.is_empty() {
+                diff.holders.push(HoldersChange { bag: bag.to_string(), gained, lost });
+            }
         }
 
-        self.names.get(bag_desc).unwrap().clone()
+        diff
     }
 
-    pub fn from<T: BufRead>(input: T) -> BagGraph {
-        let mut result = BagGraph::default();
+    pub fn from<T: BufRead>(input: T) -> Result<BagGraph, Error> {
+        let mut builder = BagGraphBuilder::default();
 
-        input.lines().for_each(|line| {
-            let rule = parse_rule(line.unwrap());
+        for line in numbered_lines(input) {
+            let (line_no, text) = line?;
+            let rule = parse_rule(text).map_err(|e| e.on_line(line_no))?;
+
+            let idx = builder.define(&rule.desc, line_no);
 
             for (held_bag, count) in &rule.can_hold {
-                result.link(&rule.desc, held_bag, *count);
+                builder.link(idx, held_bag, *count);
+            }
+        }
+
+        Ok(builder.build())
+    }
+
+    // Bags with a rule, in the order of their first rule.
+    fn rule_order(&self) -> Vec<BagIndex> {
+        let mut defined: Vec<BagIndex> = (0..self.bags.len())
+            .filter(|idx| !self.bags[*idx].defined_on.is_empty())
+            .collect();
+        defined.sort_by_key(|idx| self.bags[*idx].defined_on[0]);
+
+        defined
+    }
+
+    // A Graphviz digraph of the rules, with held counts as edge labels. Bags
+    // are listed in the order they are first mentioned, and edges rule by
+    // rule in clause order, so the same rules always give the same output.
+    pub fn to_dot(&self, highlight: Option<Highlight>) -> Result<String, UnknownBag> {
+        let mut marked = vec![false; self.bags.len()];
+
+        if let Some(highlight) = highlight {
+            let (bag, reachable) = match highlight {
+                Highlight::From(bag) => (bag, self.descendants(bag)?),
+                Highlight::To(bag) => (bag, self.ancestors(bag)?),
+            };
+
+            for name in std::iter::once(bag).chain(reachable) {
+                marked[self.names.get(name).unwrap()] = true;
+            }
+        }
+
+        // Names are quoted as they are, since they are only ever words.
+        let mut out = String::from("digraph bags {\n");
+
+        for (idx, marked) in marked.iter().enumerate() {
+            let attrs = if *marked { " [style=filled, fillcolor=gold]" } else { "" };
+            out.push_str(&format!("    \"{}\"{};\n", self.name(idx), attrs));
+        }
+
+        for idx in self.rule_order() {
+            for edge in self.edges(idx) {
+                let mut attrs = format!("label=\"{}\"", edge.count);
+                if marked[idx] && marked[edge.idx] {
+                    attrs.push_str(", color=red, penwidth=2");
+                }
+
+                out.push_str(&format!("    \"{}\" -> \"{}\" [{}];\n", self.name(idx), self.name(edge.idx), attrs));
             }
-        });
+        }
 
-        result
+        out.push_str("}\n");
+        Ok(out)
+    }
+
+    // The rules as a JSON list of `{"bag": ..., "contains": [{"count": ...,
+    // "bag": ...}]}` objects, in the order they were read. Bags which are
+    // held but have no rule are left out.
+    pub fn to_json(&self) -> String {
+        let rules: Vec<JsonRule> = self.rule_order().into_iter()
+            .map(|idx| JsonRule {
+                bag: self.name(idx).to_string(),
+                contains: self.edges(idx).iter()
+                    .map(|edge| JsonContents { count: edge.count, bag: self.name(edge.idx).to_string() })
+                    .collect(),
+            })
+            .collect();
+
+        serde_json::to_string_pretty(&rules).unwrap()
+    }
+
+    // Reads rules written by `to_json`, checking them like `from` checks
+    // rules in text. For `validate`, each rule counts as defined on the line
+    // of its 1-indexed position in the list.
+    pub fn from_json(input: &str) -> Result<BagGraph, Error> {
+        let rules: Vec<JsonRule> = serde_json::from_str(input).map_err(|e| Error::json(&e, input))?;
+
+        let mut builder = BagGraphBuilder::default();
+
+        for (position, rule) in rules.iter().enumerate() {
+            check_bag_name(&rule.bag).map_err(|reason| Error::invalid(format!("[{}].bag", position), reason))?;
+            let idx = builder.define(&rule.bag, position + 1);
+            let mut listed = HashSet::new();
+
+            for (n, held) in rule.contains.iter().enumerate() {
+                let path = format!("[{}].contains[{}]", position, n);
+
+                if held.count == 0 {
+                    return Err(Error::invalid(path + ".count", "a bag can't hold 0 of a bag"));
+                }
+
+                check_bag_name(&held.bag).map_err(|reason| Error::invalid(format!("{}.bag", path), reason))?;
+                if !listed.insert(&held.bag) {
+                    return Err(Error::invalid(path + ".bag", format!("{} bags are listed twice", held.bag)));
+                }
+
+                builder.link(idx, &held.bag, held.count);
+            }
+        }
+
+        Ok(builder.build())
+    }
+}
+
+pub struct Day07;
+
+impl Solution for Day07 {
+    fn part_a(&self, input: &mut dyn BufRead) -> Result<String, Error> {
+        let bags = BagGraph::from(input)?.could_hold("shiny gold")
+            .map_err(|e| Error::NoAnswer(e.to_string()))?;
+
+        Ok(bags.len().to_string())
+    }
+
+    fn part_b(&self, input: &mut dyn BufRead) -> Result<String, Error> {
+        let count = BagGraph::from(input)?.inner_bag_count("shiny gold")
+            .map_err(|e| Error::NoAnswer(e.to_string()))?;
+
+        Ok(count.to_string())
     }
 }
 
@@ -143,19 +1198,19 @@ vibrant plum bags contain 5 faded blue bags, 6 dotted black bags.
 faded blue bags contain no other bags.
 dotted black bags contain no other bags.");
 
-    let mut ruleset = BagGraph::from(input);
+    let ruleset = BagGraph::from(input).unwrap();
 
-    assert_eq!(true, ruleset.can_eventually_hold("light red", "shiny gold"));
-    assert_eq!(true, ruleset.can_eventually_hold("dark orange", "shiny gold"));
-    assert_eq!(true, ruleset.can_eventually_hold("bright white", "shiny gold"));
-    assert_eq!(true, ruleset.can_eventually_hold("muted yellow", "shiny gold"));
-    assert_eq!(false, ruleset.can_eventually_hold("shiny gold", "shiny gold"));
-    assert_eq!(false, ruleset.can_eventually_hold("dark olive", "shiny gold"));
-    assert_eq!(false, ruleset.can_eventually_hold("vibrant plum", "shiny gold"));
-    assert_eq!(false, ruleset.can_eventually_hold("faded blue", "shiny gold"));
-    assert_eq!(false, ruleset.can_eventually_hold("dotted black", "shiny gold"));
+    assert_eq!(Ok(true), ruleset.can_eventually_hold("light red", "shiny gold"));
+    assert_eq!(Ok(true), ruleset.can_eventually_hold("dark orange", "shiny gold"));
+    assert_eq!(Ok(true), ruleset.can_eventually_hold("bright white", "shiny gold"));
+    assert_eq!(Ok(true), ruleset.can_eventually_hold("muted yellow", "shiny gold"));
+    assert_eq!(Ok(false), ruleset.can_eventually_hold("shiny gold", "shiny gold"));
+    assert_eq!(Ok(false), ruleset.can_eventually_hold("dark olive", "shiny gold"));
+    assert_eq!(Ok(false), ruleset.can_eventually_hold("vibrant plum", "shiny gold"));
+    assert_eq!(Ok(false), ruleset.can_eventually_hold("faded blue", "shiny gold"));
+    assert_eq!(Ok(false), ruleset.can_eventually_hold("dotted black", "shiny gold"));
 
-    let mut list = ruleset.could_hold("shiny gold");
+    let mut list = ruleset.could_hold("shiny gold").unwrap();
     list.sort();
 
     assert_eq!(
@@ -163,50 +1218,407 @@ dotted black bags contain no other bags.");
         list
     );
 
-    assert_eq!(32, ruleset.inner_bag_count("shiny gold"));
+    assert_eq!(Ok(32), ruleset.inner_bag_count("shiny gold"));
+    assert_eq!(vec!["light red", "dark orange"], ruleset.roots().collect::<Vec<&str>>());
+    assert_eq!(vec!["faded blue", "dotted black"], ruleset.leaves().collect::<Vec<&str>>());
+
+    let mut descendants: Vec<&str> = ruleset.descendants("muted yellow").unwrap().collect();
+    descendants.sort();
+    assert_eq!(vec!["dark olive", "dotted black", "faded blue", "shiny gold", "vibrant plum"], descendants);
+    assert_eq!(0, ruleset.ancestors("light red").unwrap().count());
+
+    let unknown = UnknownBag { name: "shiny glod".to_string(), suggestions: vec!["shiny gold".to_string()] };
+    assert_eq!(Err(QueryError::UnknownBag(unknown.clone())), ruleset.can_eventually_hold("shiny glod", "light red"));
+    assert_eq!(Err(CountError::UnknownBag(unknown)), ruleset.inner_bag_count("shiny glod"));
+    assert_eq!(
+        "unknown bag: dark orang (did you mean dark orange?)",
+        ruleset.could_hold("dark orang").unwrap_err().to_string()
+    );
+    assert!(ruleset.descendants("mauve").is_err());
+    assert_eq!(None, ruleset.names.get("shiny glod"));
+    assert_eq!(Some(&BagTotal::Count(0)), ruleset.inner_bag_counts().get("faded blue"));
+    assert!(ruleset.validate().is_empty());
+}
+
+#[test]
+fn test_stable_indices() {
+    let input = "\
+light red bags contain 1 bright white bag, 2 muted yellow bags.
+bright white bags contain 1 light red bag, 1 shiny gold bag.
+muted yellow bags contain 2 shiny gold bags, 9 faded blue bags.
+shiny gold bags contain 1 bright white bag.
+faded blue bags contain no other bags.";
+
+    let first = BagGraph::from(std::io::Cursor::new(input)).unwrap();
+    let second = BagGraph::from(std::io::Cursor::new(input)).unwrap();
+
+    assert_eq!(first.names.names, second.names.names);
+    assert_eq!(
+        vec!["light red", "bright white", "muted yellow", "shiny gold", "faded blue"],
+        first.names.names.iter().map(|name| &**name).collect::<Vec<&str>>()
+    );
+
+    for idx in 0..first.bags.len() {
+        let edges = |graph: &BagGraph| graph.edges(idx).iter().map(|edge| (edge.idx, edge.count)).collect::<Vec<_>>();
+
+        assert_eq!(edges(&first), edges(&second));
+        assert_eq!(first.holders(idx), second.holders(idx));
+    }
+
+    assert_eq!(vec![(3, 2), (4, 9)], first.edges(2).iter().map(|edge| (edge.idx, edge.count)).collect::<Vec<_>>());
+    assert_eq!(first.find_cycles(), second.find_cycles());
+    assert_eq!(2, first.find_cycles().len());
+}
+
+#[test]
+fn test_packing_tree() {
+    let input = std::io::Cursor::new("\
+shiny gold bags contain 1 dark olive bag, 2 vibrant plum bags.
+dark olive bags contain 3 faded blue bags, 4 dotted black bags.
+vibrant plum bags contain 5 faded blue bags, 6 dotted black bags.
+faded blue bags contain no other bags.
+dotted black bags contain no other bags.");
+
+    let ruleset = BagGraph::from(input).unwrap();
+    let tree = ruleset.packing_tree("shiny gold").unwrap();
+
+    assert_eq!(32, tree.subtotal);
+    assert_eq!(2, tree.depth());
+    assert_eq!(10, tree.contents[1].contents[1].total);
+    assert_eq!("\
+1 shiny gold (1 in total): 32 inside each
+  1 dark olive (1 in total): 7 inside each
+    4 dotted black (4 in total)
+    3 faded blue (3 in total)
+  2 vibrant plum (2 in total): 11 inside each
+    6 dotted black (12 in total)
+    5 faded blue (10 in total)
+", tree.to_string());
+
+    let leaf = ruleset.packing_tree("faded blue").unwrap();
+    assert_eq!((0, 0), (leaf.subtotal, leaf.depth()));
+    assert!(ruleset.packing_tree("shiny glod").is_err());
+}
+
+#[test]
+fn test_deep_packing_tree() {
+    // Deep enough to overflow a test thread's stack if expanding, measuring
+    // or dropping the tree recursed once per level.
+    let mut rules: Vec<String> = (0..20_000)
+        .map(|level| format!("level {} bags contain 1 level {} bag.", level, level + 1))
+        .collect();
+    rules.push("level 20000 bags contain no other bags.".to_string());
+
+    let ruleset = BagGraph::from(std::io::Cursor::new(rules.join("\n"))).unwrap();
+    let tree = ruleset.packing_tree("level 0").unwrap();
+
+    assert_eq!(20_000, tree.depth());
+    assert_eq!(20_000, tree.subtotal);
+
+    // Totals are only worked out once, so asking for every one is quick.
+    for level in 0..=20_000 {
+        assert_eq!(Ok(20_000 - level), ruleset.inner_bag_count(&format!("level {}", level)));
+    }
+}
+
+#[test]
+fn test_validate() {
+    let input = std::io::Cursor::new("\
+light red bags contain 1 bright white bag.
+bright white bags contain 2 muted yellow bags.
+muted yellow bags contain 1 light red bag.
+shiny gold bags contain 3 faded blue bags.
+shiny gold bags contain no other bags.");
+
+    let ruleset = BagGraph::from(input).unwrap();
+    let cycle = Cycle(vec!["light red".to_string(), "bright white".to_string(),
+                           "muted yellow".to_string(), "light red".to_string()]);
+
+    assert_eq!(
+        vec![
+            Problem::Cycle(cycle.clone()),
+            Problem::Undefined("faded blue".to_string()),
+            Problem::Duplicate { bag: "shiny gold".to_string(), lines: vec![4, 5] },
+        ],
+        ruleset.validate()
+    );
+
+    assert_eq!(Err(CountError::Cycle(cycle.clone())), ruleset.inner_bag_count("light red"));
+    assert_eq!(Err(QueryError::Cycle(cycle.clone())), ruleset.could_hold("light red"));
+    assert_eq!(Err(QueryError::Cycle(cycle)), ruleset.can_eventually_hold("light red", "shiny gold"));
+
+    // Queries which can't reach the cycle still work.
+    assert_eq!(Ok(true), ruleset.can_eventually_hold("shiny gold", "faded blue"));
+    assert_eq!(Ok(3), ruleset.inner_bag_count("shiny gold"));
+    assert_eq!(Ok(3), ruleset.packing_tree("shiny gold").map(|tree| tree.subtotal));
+    assert_eq!(Some(&BagTotal::Cyclic), ruleset.inner_bag_counts().get("muted yellow"));
+    assert_eq!(Ok(vec!["shiny gold".to_string()]), ruleset.could_hold("faded blue"));
+
+    // Traversals still terminate, and include the starting bag.
+    let mut ancestors: Vec<&str> = ruleset.ancestors("light red").unwrap().collect();
+    ancestors.sort();
+    assert_eq!(vec!["bright white", "light red", "muted yellow"], ancestors);
+}
+
+#[test]
+fn test_export() {
+    let input = "\
+shiny gold bags contain 2 vibrant plum bags, 1 dark olive bag.
+dark olive bags contain 3 faded blue bags.
+vibrant plum bags contain 5 faded blue bags.
+faded blue bags contain no other bags.";
+
+    let ruleset = BagGraph::from(std::io::Cursor::new(input)).unwrap();
+
+    assert_eq!("\
+digraph bags {
+    \"shiny gold\" [style=filled, fillcolor=gold];
+    \"vibrant plum\";
+    \"dark olive\" [style=filled, fillcolor=gold];
+    \"faded blue\";
+    \"shiny gold\" -> \"vibrant plum\" [label=\"2\"];
+    \"shiny gold\" -> \"dark olive\" [label=\"1\", color=red, penwidth=2];
+    \"dark olive\" -> \"faded blue\" [label=\"3\"];
+    \"vibrant plum\" -> \"faded blue\" [label=\"5\"];
+}
+", ruleset.to_dot(Some(Highlight::To("dark olive"))).unwrap());
+
+    let json = ruleset.to_json();
+    let bags: Vec<String> = serde_json::from_str::<Vec<JsonRule>>(&json).unwrap().into_iter().map(|rule| rule.bag).collect();
+    assert_eq!(vec!["shiny gold", "dark olive", "vibrant plum", "faded blue"], bags);
+    assert_eq!(json, BagGraph::from(std::io::Cursor::new(input)).unwrap().to_json());
+
+    let read = BagGraph::from_json(&json).unwrap();
+
+    assert_eq!(json, read.to_json());
+    assert_eq!(Ok(16), read.inner_bag_count("shiny gold"));
+    assert!(read.validate().is_empty());
+
+    match BagGraph::from_json("[\n  {\"bag\": \"shiny gold\", \"contains\": 3}\n]") {
+        Err(Error::Parse { line, .. }) => assert_eq!(2, line),
+        other => panic!("Expected parse error, got {:?}", other),
+    }
+
+    let invalid = |json: &str| match BagGraph::from_json(json) {
+        Err(Error::Invalid { path, .. }) => path,
+        other => panic!("Expected invalid rule, got {:?}", other),
+    };
+
+    assert_eq!("[0].bag", invalid(r#"[{"bag": "", "contains": []}]"#));
+    assert_eq!("[1].bag", invalid(r#"[{"bag": "red", "contains": []}, {"bag": "say \"hi\"", "contains": []}]"#));
+    assert_eq!("[0].contains[0].count", invalid(r#"[{"bag": "red", "contains": [{"count": 0, "bag": "blue"}]}]"#));
+    assert_eq!(
+        "[0].contains[1].bag",
+        invalid(r#"[{"bag": "red", "contains": [{"count": 1, "bag": "blue"}, {"count": 2, "bag": "blue"}]}]"#)
+    );
+}
+
+#[test]
+fn test_diff() {
+    let before = BagGraph::from(std::io::Cursor::new("\
+light red bags contain 1 bright white bag.
+bright white bags contain 1 shiny gold bag, 1 pale lime bag.
+shiny gold bags contain 2 faded blue bags.
+faded blue bags contain no other bags.
+pale lime bags contain no other bags.")).unwrap();
+
+    let after = BagGraph::from(std::io::Cursor::new("\
+light red bags contain 1 bright white bag, 1 shiny gold bag.
+bright white bags contain no other bags.
+shiny gold bags contain 3 faded blue bags.
+faded blue bags contain no other bags.
+dark orange bags contain 1 faded blue bag, 2 wavy cyan bags.
+wavy cyan bags contain no other bags.")).unwrap();
+
+    let diff = before.diff(&after);
+
+    assert_eq!(vec!["dark orange", "wavy cyan"], diff.added);
+    assert_eq!(vec!["pale lime"], diff.removed);
+    assert_eq!("\
+added bag: dark orange
+added bag: wavy cyan
+removed bag: pale lime
+bright white no longer holds pale lime
+bright white no longer holds shiny gold
+dark orange now holds 1 faded blue
+dark orange now holds 2 wavy cyan
+light red now holds 1 shiny gold
+shiny gold holds 3 faded blue (was 2)
+bright white holds 0 bags (was 4)
+shiny gold holds 3 bags (was 2)
+faded blue can now be held by dark orange
+faded blue can no longer be held by bright white
+pale lime can no longer be held by bright white, light red
+shiny gold can no longer be held by bright white
+wavy cyan can now be held by dark orange
+", diff.to_string());
+
+    assert!(before.diff(&before).is_empty());
+
+    // A cycle on one side only leaves the bags holding it without a count.
+    let cyclic = BagGraph::from(std::io::Cursor::new("\
+light red bags contain 1 bright white bag.
+bright white bags contain 1 shiny gold bag, 1 pale lime bag.
+shiny gold bags contain 2 faded blue bags, 1 bright white bag.
+faded blue bags contain no other bags.
+pale lime bags contain no other bags.")).unwrap();
+
+    assert_eq!("\
+shiny gold now holds 1 bright white
+bright white holds endless bags (was 4)
+light red holds endless bags (was 5)
+shiny gold holds endless bags (was 2)
+bright white can now be held by bright white, shiny gold
+pale lime can now be held by shiny gold
+shiny gold can now be held by shiny gold
+", before.diff(&cyclic).to_string());
 }
 
-fn parse_rule(input: String) -> BagRule {
-    let bag = regex_captures!(r"^(\w+ \w+) bags", &input).unwrap()[1].to_string();
+#[test]
+fn test_inner_bag_count_overflow() {
+    // Each level holds two of the next, so the count doubles (plus two) per
+    // level: level 6 holds 2^64 - 2 bags, and level 5 twice that.
+    let mut rules: Vec<String> = (0..69)
+        .map(|level| format!("level {} bags contain 2 level {} bags.", level, level + 1))
+        .collect();
+    rules.push("level 69 bags contain no other bags.".to_string());
+
+    let ruleset = BagGraph::from(std::io::Cursor::new(rules.join("\n"))).unwrap();
+
+    assert_eq!(Ok(u64::MAX - 1), ruleset.inner_bag_count("level 6"));
+    assert_eq!(Err(CountError::Overflow { bag: "level 0".to_string() }), ruleset.inner_bag_count("level 0"));
+    // One bag overflowing doesn't stop the others being counted.
+    let counts = ruleset.inner_bag_counts();
+    assert_eq!(Some(&BagTotal::Overflow), counts.get("level 5"));
+    assert_eq!(Some(&BagTotal::Count(u64::MAX - 1)), counts.get("level 6"));
+    assert_eq!(Some(&BagTotal::Count(0)), counts.get("level 69"));
+}
+
+// Colours are one or more words, separated by single spaces, as `parse_rule`
+// reads them.
+fn check_bag_name(name: &str) -> Result<(), String> {
+    match regex_captures!(r"^\w+(?: \w+)*$", name) {
+        Some(_) => Ok(()),
+        None => Err(format!("`{}` isn't a bag colour of one or more words", name)),
+    }
+}
+
+// Parses `<colour> bags contain <contents>.`, where the contents are either
+// `no other bags` or a comma-separated list of `<count> <colour> bag(s)`.
+// Colours are one or more words.
+fn parse_rule(input: String) -> Result<BagRule, Error> {
+    let text = input.trim_end();
+
+    let (bag, contents_start) = match regex_captures!(r"^(\w+(?: \w+)*) bags contain ", text) {
+        Some(captures) => (captures[1].to_string(), captures.get(0).unwrap().end()),
+        None => return Err(Error::parse(1, &input, "expected `<colour> bags contain`")),
+    };
+
+    let contents = match text[contents_start..].strip_suffix('.') {
+        Some(contents) => contents,
+        None => return Err(Error::parse(text.len() + 1, &input, "expected `.` at the end of the rule")),
+    };
+
+    let mut can_hold = vec![];
+    let mut listed = HashSet::new();
+
+    if contents == "no other bags" {
+        return Ok(BagRule { desc: bag, can_hold });
+    }
+
+    let mut clause_start = contents_start;
+
+    for clause in contents.split(',') {
+        let offset = clause_start + (clause.len() - clause.trim_start().len());
+        let clause = clause.trim_start();
+        clause_start += clause.len() + (offset - clause_start) + 1;
 
-    let bag_re: Regex = Regex::new(r"(\d+) (\w+ \w+) bags?").unwrap();
+        let captures = regex_captures!(r"^(\d+) (\w+(?: \w+)*) bags?$", clause)
+            .ok_or_else(|| Error::parse(offset + 1, &input, "expected `<count> <colour> bag(s)`"))?;
 
-    BagRule {
-        desc: bag,
-        can_hold: bag_re.captures_iter(&input)
-            .map(|c| (c[2].to_string(), c[1].parse::<u8>().unwrap()))
-            .collect(),
+        let count = captures.get(1).unwrap();
+        let value = match count.as_str().parse::<u64>() {
+            Ok(0) => return Err(Error::parse(offset + 1, &input, "a bag can't hold 0 of a bag")),
+            Ok(value) => value,
+            Err(e) => return Err(Error::parse(offset + 1, &input, format!("invalid count: {}", e))),
+        };
+
+        let held = captures[2].to_string();
+        if !listed.insert(held.clone()) {
+            let column = offset + captures.get(2).unwrap().start() + 1;
+            return Err(Error::parse(column, &input, format!("{} bags are listed twice", held)));
+        }
+
+        can_hold.push((held, value));
     }
+
+    Ok(BagRule { desc: bag, can_hold })
 }
 
 #[test]
 fn test_parse_rule() {
     assert_eq!(
-        parse_rule("shiny aqua bags contain 1 dark white bag.\n".to_string()),
+        parse_rule("shiny aqua bags contain 1 dark white bag.\n".to_string()).unwrap(),
         BagRule {
             desc: "shiny aqua".to_string(),
-            can_hold: hashmap! { "dark white".to_string() => 1 },
+            can_hold: vec![("dark white".to_string(), 1)],
         }
     );
 
     assert_eq!(
-        parse_rule("dark purple bags contain 1 wavy indigo bag, 3 bright black bags, 3 dotted teal bags.".to_string()),
+        parse_rule("dark purple bags contain 1 wavy indigo bag, 3 bright black bags, 3 dotted teal bags.".to_string()).unwrap(),
         BagRule {
             desc: "dark purple".to_string(),
-            can_hold: hashmap! {
-                "wavy indigo".to_string() => 1,
-                "bright black".to_string() => 3,
-                "dotted teal".to_string() => 3,
-            },
+            can_hold: vec![
+                ("wavy indigo".to_string(), 1),
+                ("bright black".to_string(), 3),
+                ("dotted teal".to_string(), 3),
+            ],
         }
     );
 
     assert_eq!(
-        parse_rule("dull silver bags contain no other bags.".to_string()),
+        parse_rule("dull silver bags contain no other bags.".to_string()).unwrap(),
         BagRule {
             desc: "dull silver".to_string(),
-            can_hold: hashmap! {},
+            can_hold: vec![],
+        }
+    );
+
+    assert_eq!(
+        parse_rule("pale sky blue bags contain 1 gold bag, 4000000000 very dark teal bags.  \t".to_string()).unwrap(),
+        BagRule {
+            desc: "pale sky blue".to_string(),
+            can_hold: vec![
+                ("gold".to_string(), 1),
+                ("very dark teal".to_string(), 4_000_000_000),
+            ],
         }
     );
 }
 
+#[test]
+fn test_parse_rule_errors() {
+    assert!(parse_rule("shiny contain 1 dark white bag.".to_string()).is_err());
+
+    match parse_rule("shiny aqua bags contain 30000000000000000000 dark white bags.".to_string()) {
+        Err(Error::Parse { column, .. }) => assert_eq!(25, column),
+        other => panic!("Expected parse error, got {:?}", other),
+    }
+
+    let columns = [
+        "shiny aqua bags contain 1 dark white bag",
+        "shiny aqua bags contain 1 dark white bag, some red bags.",
+        "shiny aqua bags contain 1 dark white bag,, 2 red bags.",
+        "shiny aqua bags contain 0 dark white bags.",
+        "shiny aqua bags contain 1 red bag, 2 red bags.",
+        "shiny aqua bags contain no bags.",
+    ].iter()
+        .map(|line| match parse_rule(line.to_string()) {
+            Err(Error::Parse { column, .. }) => column,
+            other => panic!("Expected parse error, got {:?}", other),
+        })
+        .collect::<Vec<usize>>();
+
+    assert_eq!(vec![41, 43, 42, 25, 38, 25], columns);
+}
@@ -0,0 +1,250 @@
+// Seeded generation of bag rules, for producing large or adversarial inputs
+// in the format `BagGraph::from` reads.
+//
+// Bags are spread over `depth + 1` levels, and each bag holds bags from
+// deeper levels only, so the rules are acyclic unless cycles are asked for.
+// Every bag above the bottom level holds at least one bag from the level
+// directly below it, so the deepest packing is exactly `depth` levels.
+
+use std::fmt;
+
+#[derive(Clone, Debug)]
+pub struct GeneratorOptions {
+    pub seed: u64,
+    pub bags: usize,
+    // The most distinct bags one bag holds.
+    pub branching: usize,
+    pub depth: usize,
+    // Counts are between 1 and this, inclusive.
+    pub max_count: u64,
+    // Extra edges leading back up from a bag to one holding it, each of
+    // which closes a cycle. Exactly this many are added, unless the rules
+    // are too small to have room for them.
+    pub cycles: usize,
+}
+
+impl Default for GeneratorOptions {
+    fn default() -> GeneratorOptions {
+        GeneratorOptions { seed: 0, bags: 100, branching: 3, depth: 5, max_count: 5, cycles: 0 }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct GeneratedRule {
+    pub bag: String,
+    pub contents: Vec<(u64, String)>,
+}
+
+impl fmt::Display for GeneratedRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} bags contain ", self.bag)?;
+
+        if self.contents.is_empty() {
+            return write!(f, "no other bags.");
+        }
+
+        for (idx, (count, bag)) in self.contents.iter().enumerate() {
+            let separator = if idx == 0 { "" } else { ", " };
+            let plural = if *count == 1 { "" } else { "s" };
+            write!(f, "{}{} {} bag{}", separator, count, bag, plural)?;
+        }
+
+        write!(f, ".")
+    }
+}
+
+// SplitMix64, so a seed gives the same rules everywhere.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // A number in `low..=high`.
+    fn between(&mut self, low: u64, high: u64) -> u64 {
+        low + self.next() % (high - low + 1)
+    }
+
+    fn index(&mut self, len: usize) -> usize {
+        (self.next() % len as u64) as usize
+    }
+}
+
+const ADJECTIVES: [&str; 12] = [
+    "bright", "clear", "dark", "dim", "dotted", "drab", "dull", "faded", "light", "muted", "pale", "shiny",
+];
+
+const COLOURS: [&str; 12] = [
+    "beige", "black", "blue", "bronze", "coral", "gold", "green", "olive", "orange", "plum", "red", "white",
+];
+
+// How many tries `generate` makes at each cycle before deciding there is no
+// room for more.
+const ATTEMPTS_PER_CYCLE: usize = 100;
+
+// Two words for the first 144 bags, then a numbered third word.
+fn bag_name(idx: usize) -> String {
+    let name = format!("{} {}", ADJECTIVES[idx % ADJECTIVES.len()], COLOURS[idx / ADJECTIVES.len() % COLOURS.len()]);
+
+    match idx / (ADJECTIVES.len() * COLOURS.len()) {
+        0 => name,
+        round => format!("{} mark{}", name, round),
+    }
+}
+
+// One rule per bag, in shuffled order.
+pub fn generate(options: &GeneratorOptions) -> Vec<GeneratedRule> {
+    let mut rng = Rng(options.seed);
+    let levels = options.depth + 1;
+    let bag_count = options.bags.max(levels);
+
+    // Bags are numbered by level, with each level's bags contiguous.
+    let level_start = |level: usize| level * bag_count / levels;
+
+    let mut contents: Vec<Vec<(u64, usize)>> = vec![vec![]; bag_count];
+
+    for level in 0..options.depth {
+        for bag_contents in &mut contents[level_start(level)..level_start(level + 1)] {
+            let next_level = level_start(level + 1)..level_start(level + 2);
+            let mut held = vec![next_level.start + rng.index(next_level.len())];
+
+            let deeper = level_start(level + 1)..bag_count;
+            let wanted = rng.between(1, options.branching.max(1) as u64) as usize;
+
+            for _ in 1..wanted {
+                let candidate = deeper.start + rng.index(deeper.len());
+                if !held.contains(&candidate) {
+                    held.push(candidate);
+                }
+            }
+
+            *bag_contents = held.into_iter().map(|idx| (rng.between(1, options.max_count.max(1)), idx)).collect();
+        }
+    }
+
+    // Walks only follow the acyclic edges, so each new edge leads back up.
+    let acyclic: Vec<usize> = contents.iter().map(Vec::len).collect();
+    let mut added = 0;
+
+    for _ in 0..options.cycles * ATTEMPTS_PER_CYCLE {
+        if added == options.cycles {
+            break;
+        }
+
+        // Walk down from a bag which holds something, then lead back to it,
+        // unless that is where the walk started or the edge already exists.
+        let top = rng.index(level_start(options.depth).max(1));
+        let mut bag = top;
+
+        for _ in 0..=rng.index(levels) {
+            match acyclic[bag] {
+                0 => break,
+                len => bag = contents[bag][rng.index(len)].1,
+            }
+        }
+
+        if bag != top && contents[bag].iter().all(|(_, held)| *held != top) {
+            let count = rng.between(1, options.max_count.max(1));
+            contents[bag].push((count, top));
+            added += 1;
+        }
+    }
+
+    let mut order: Vec<usize> = (0..bag_count).collect();
+    for idx in (1..order.len()).rev() {
+        order.swap(idx, rng.index(idx + 1));
+    }
+
+    order.into_iter()
+        .map(|bag| GeneratedRule {
+            bag: bag_name(bag),
+            contents: contents[bag].iter().map(|(count, held)| (*count, bag_name(*held))).collect(),
+        })
+        .collect()
+}
+
+// The rules as lines of text.
+pub fn generate_text(options: &GeneratorOptions) -> String {
+    generate(options).iter().map(|rule| format!("{}\n", rule)).collect()
+}
+
+#[cfg(test)]
+fn reference_count(rules: &[GeneratedRule], bag: &str) -> u64 {
+    let rule = rules.iter().find(|rule| rule.bag == bag).unwrap();
+
+    rule.contents.iter().map(|(count, held)| count * (1 + reference_count(rules, held))).sum()
+}
+
+#[test]
+fn test_generate() {
+    use super::BagGraph;
+
+    for seed in 0..20 {
+        let options = GeneratorOptions { seed, bags: 60, branching: 4, depth: 6, max_count: 9, cycles: 0 };
+        let rules = generate(&options);
+        let text = generate_text(&options);
+
+        assert_eq!(text, generate_text(&options));
+        assert_eq!(60, rules.len());
+
+        let graph = BagGraph::from(std::io::Cursor::new(&text)).unwrap();
+        assert!(graph.validate().is_empty());
+
+        for rule in &rules {
+            assert_eq!(Ok(reference_count(&rules, &rule.bag)), graph.inner_bag_count(&rule.bag));
+        }
+
+        let deepest = rules.iter().map(|rule| graph.packing_tree(&rule.bag).unwrap().depth()).max();
+        assert_eq!(Some(6), deepest);
+
+        let json = BagGraph::from_json(&graph.to_json()).unwrap();
+        assert!(graph.diff(&json).is_empty());
+    }
+
+    assert_eq!("shiny gold", bag_name(11 + 5 * 12));
+    assert_eq!("bright beige mark1", bag_name(144));
+    assert_ne!(generate_text(&GeneratorOptions::default()), generate_text(&GeneratorOptions { seed: 1, ..Default::default() }));
+}
+
+#[test]
+fn test_generate_cycles() {
+    use std::collections::BTreeSet;
+
+    use super::{BagGraph, Problem};
+
+    let edges = |rules: &[GeneratedRule]| -> BTreeSet<(String, String)> {
+        rules.iter()
+            .flat_map(|rule| rule.contents.iter().map(move |(_, held)| (rule.bag.clone(), held.clone())))
+            .collect()
+    };
+
+    for seed in 0..10 {
+        let options = GeneratorOptions { seed, bags: 300, cycles: 5, ..Default::default() };
+        let acyclic_options = GeneratorOptions { cycles: 0, ..options.clone() };
+        let acyclic_graph = BagGraph::from(std::io::Cursor::new(generate_text(&acyclic_options))).unwrap();
+        let graph = BagGraph::from(std::io::Cursor::new(generate_text(&options))).unwrap();
+
+        // Each extra edge leads from a bag to one which already held it.
+        let extra: Vec<(String, String)> = edges(&generate(&options))
+            .difference(&edges(&generate(&acyclic_options)))
+            .cloned()
+            .collect();
+        assert_eq!(5, extra.len());
+
+        for (bag, top) in &extra {
+            assert_eq!(Ok(true), acyclic_graph.can_eventually_hold(top, bag));
+        }
+
+        assert!(graph.validate().into_iter().any(|problem| matches!(problem, Problem::Cycle(_))));
+    }
+
+    // A single level has nowhere to lead back to.
+    let flat = GeneratorOptions { bags: 10, depth: 0, cycles: 3, ..Default::default() };
+    assert!(generate(&flat).iter().all(|rule| rule.contents.is_empty()));
+}
@@ -1,105 +1,292 @@
 use std::io::BufRead;
 
-#[derive(Eq, PartialEq, Clone, Debug)]
-pub enum Inst {
-    Nop(i16),
-    Acc(i16),
-    Jmp(i16),
-}
+use crate::error::Error;
+use crate::numbered_lines;
+use crate::solution::Solution;
+
+pub mod asm;
+pub mod breakpoint;
+pub mod cfg;
+pub mod isa;
+pub mod repair;
+pub mod trace;
 
-type Program = Vec<Inst>;
+pub use isa::{Fault, Inst, Op, Operand, OperandError, Reg};
+use breakpoint::{Breakpoint, Hit, Revisited};
+use isa::{Cpu, Flow, parse_inst};
+use trace::{Step, Trace};
+
+pub type Program = Vec<Inst>;
 
 #[derive(Default, Clone, Debug)]
 pub struct InstMeta {
     pub execution_count: usize,
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Terminated {
+    // Executed `halt` or stepped to exactly the end of the program.
+    Normal,
+    // Jumped to an address outside the program, under `OutOfBounds::Trap`.
+    OutOfBounds { pc: i64 },
+}
+
+// What to do when a jump leaves the program, other than to exactly
+// `program.len()`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum OutOfBounds {
+    // Stop with `Terminated::OutOfBounds`.
+    #[default]
+    Trap,
+    // Continue at the target modulo the program's length.
+    Wrap,
+    // Stop as if the jump were `halt`.
+    Halt,
+}
+
+// Why `Console::run_until` returned.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum StopReason {
+    Breakpoint(Hit),
+    Terminated(Terminated),
+    Fault(Fault),
+}
+
 pub struct Console {
-    pub cycles: i32,
-    pub accumulator: i32,
+    pub cycles: usize,
+    cpu: Cpu,
     program: Vec<Inst>,
-    trace: Vec<InstMeta>,
+    meta: Vec<InstMeta>,
+    tracer: Option<Trace>,
     pc: usize,
+    terminated: Option<Terminated>,
+    fault: Option<Fault>,
+    out_of_bounds: OutOfBounds,
+}
+
+impl Default for Console {
+    fn default() -> Console {
+        Console::new()
+    }
 }
 
 impl Console {
     pub fn new() -> Console {
         Console {
             cycles: 0,
-            accumulator: 0,
+            cpu: Cpu::default(),
             program: vec![],
-            trace: vec![],
+            meta: vec![],
+            tracer: None,
             pc: 0,
+            terminated: None,
+            fault: None,
+            out_of_bounds: OutOfBounds::default(),
         }
     }
 
+    pub fn set_out_of_bounds(&mut self, policy: OutOfBounds) {
+        self.out_of_bounds = policy;
+    }
+
     pub fn load_program(&mut self, code: Program) {
-        self.program = code.clone();
-        self.trace = vec![InstMeta::default(); code.len()];
+        self.program = code;
+        self.reset();
+    }
+
+    // Rewinds to the start of the loaded program, clearing registers, output,
+    // execution counts and any trace.
+    pub fn reset(&mut self) {
+        self.meta = vec![InstMeta::default(); self.program.len()];
+        if let Some(trace) = &mut self.tracer {
+            trace.steps.clear();
+        }
         self.pc = 0;
-        self.accumulator = 0;
+        self.cycles = 0;
+        self.cpu = Cpu::default();
+        self.terminated = None;
+        self.fault = None;
     }
 
-    // Returns true is program completes successfully, false if a break
-    // condition was met.
-    pub fn run_until(&mut self, breakpoint: fn(&Inst, &InstMeta) -> bool) -> bool {
+    // Runs until the program stops or `breakpoint` fires. The breakpoint is
+    // checked before every instruction, including the first.
+    pub fn run_until<B: Breakpoint>(&mut self, mut breakpoint: B) -> StopReason {
         loop {
-            if self.pc == self.program.len() {
-                return true;
-            } else if breakpoint(self.inst(), self.meta()) {
-                return false;
-            } else {
-                self.step();
+            if let Some(terminated) = self.terminated() {
+                return StopReason::Terminated(terminated);
+            }
+
+            if let Some(fault) = self.fault {
+                return StopReason::Fault(fault);
+            }
+
+            if let Some(hit) = breakpoint.check(self) {
+                return StopReason::Breakpoint(hit);
             }
+
+            self.step();
         }
     }
 
-    fn inst(&self) -> &Inst {
-        self.program.get(self.pc).unwrap()
+    pub fn program(&self) -> &Program {
+        &self.program
     }
 
-    fn meta(&self) -> &InstMeta {
-        self.trace.get(self.pc).unwrap()
+    pub fn pc(&self) -> usize {
+        self.pc
     }
 
-    fn step(&mut self) {
-        self.trace.get_mut(self.pc).unwrap().execution_count += 1;
-        self.cycles += 1;
+    pub fn accumulator(&self) -> i64 {
+        self.cpu.get(Reg::ACC)
+    }
 
-        match self.inst() {
-            Inst::Nop(_) => self.pc += 1,
-            Inst::Acc(value) => {
-                self.accumulator += *value as i32;
-                self.pc += 1;
-            }
-            Inst::Jmp(value) => {
-                if *value > 0 {
-                    self.pc = self.pc.wrapping_add(*value as usize);
-                } else {
-                    self.pc = self.pc.wrapping_sub(value.abs() as usize);
-                }
+    pub fn register(&self, reg: Reg) -> i64 {
+        self.cpu.get(reg)
+    }
+
+    // Values written by `out` instructions, in order.
+    pub fn output(&self) -> &[i64] {
+        &self.cpu.output
+    }
+
+    // Set when an instruction could not complete; the program counter is
+    // left pointing at it.
+    pub fn fault(&self) -> Option<Fault> {
+        self.fault
+    }
+
+    // How the program ended, if it has.
+    pub fn terminated(&self) -> Option<Terminated> {
+        if self.terminated.is_none() && self.pc == self.program.len() {
+            Some(Terminated::Normal)
+        } else {
+            self.terminated
+        }
+    }
+
+    pub fn is_terminated(&self) -> bool {
+        self.terminated().is_some()
+    }
+
+    // The instruction at the program counter, or None if the program counter
+    // is outside the program.
+    pub fn inst(&self) -> Option<&Inst> {
+        self.program.get(self.pc)
+    }
+
+    pub fn meta(&self) -> Option<&InstMeta> {
+        self.meta_at(self.pc)
+    }
+
+    pub fn meta_at(&self, addr: usize) -> Option<&InstMeta> {
+        self.meta.get(addr)
+    }
+
+    // Starts recording every step from now on.
+    pub fn enable_trace(&mut self) {
+        if self.tracer.is_none() {
+            self.tracer = Some(Trace::default());
+        }
+    }
+
+    pub fn trace(&self) -> Option<&Trace> {
+        self.tracer.as_ref()
+    }
+
+    // Executes the instruction at the program counter. Returns false if the
+    // program has already terminated or faulted, or the instruction faults,
+    // in which case the program counter is left unchanged.
+    pub fn step(&mut self) -> bool {
+        if self.is_terminated() || self.fault.is_some() {
+            return false;
+        }
+
+        let inst = match self.program.get(self.pc) {
+            Some(inst) => inst,
+            None => return false,
+        };
+
+        let acc_before = self.cpu.get(Reg::ACC);
+
+        let flow = match inst.execute(&mut self.cpu) {
+            Ok(flow) => flow,
+            Err(fault) => {
+                self.fault = Some(fault);
+                return false;
             }
+        };
+
+        if let Some(trace) = &mut self.tracer {
+            trace.steps.push(Step {
+                cycle: self.cycles,
+                pc: self.pc,
+                inst: inst.clone(),
+                acc_before,
+                acc_after: self.cpu.get(Reg::ACC),
+            });
+        }
+
+        self.meta[self.pc].execution_count += 1;
+        self.cycles += 1;
+
+        match flow {
+            Flow::Next => self.pc += 1,
+            Flow::Jump(offset) => self.jump(offset),
+            Flow::Halt => self.terminated = Some(Terminated::Normal),
         }
+
+        true
     }
-}
 
-pub fn parse_program<T: BufRead>(input: T) -> Program {
-    input.lines().map(|line| {
-        let line = line.unwrap();
-        let mut fields = line.split(" ");
+    fn jump(&mut self, offset: i64) {
+        let len = self.program.len() as i64;
+        let target = (self.pc as i64).saturating_add(offset);
 
-        let inst = fields.next().unwrap();
-        let arg = fields.next().unwrap();
+        if (0..=len).contains(&target) {
+            self.pc = target as usize;
+            return;
+        }
 
-        match inst {
-            "nop" => Inst::Nop(arg.parse::<i16>().unwrap()),
-            "acc" => Inst::Acc(arg.parse::<i16>().unwrap()),
-            "jmp" => Inst::Jmp(arg.parse::<i16>().unwrap()),
-            _ => panic!(format!("Unexpected instruction: {} {}", inst, arg))
+        match self.out_of_bounds {
+            OutOfBounds::Trap => self.terminated = Some(Terminated::OutOfBounds { pc: target }),
+            OutOfBounds::Wrap => self.pc = target.rem_euclid(len) as usize,
+            OutOfBounds::Halt => self.terminated = Some(Terminated::Normal),
         }
+    }
+}
+
+pub fn parse_program<T: BufRead>(input: T) -> Result<Program, Error> {
+    numbered_lines(input).map(|line| {
+        let (line_no, text) = line?;
+
+        parse_inst(&text).map_err(|e| e.on_line(line_no))
     }).collect()
 }
 
+pub struct Day08;
+
+impl Solution for Day08 {
+    fn part_a(&self, input: &mut dyn BufRead) -> Result<String, Error> {
+        let mut console = Console::new();
+        console.load_program(parse_program(input)?);
+        console.run_until(Revisited);
+
+        Ok(console.accumulator().to_string())
+    }
+
+    fn part_b(&self, input: &mut dyn BufRead) -> Result<String, Error> {
+        let program = parse_program(input)?;
+
+        let patches = repair::find_repairs(&program, &repair::RepairOptions::default())
+            .map_err(|e| Error::NoAnswer(e.to_string()))?;
+
+        match patches.first() {
+            Some(patch) => Ok(patch.accumulator.to_string()),
+            None => Err(Error::NoAnswer("no single instruction swap terminates the program".to_string())),
+        }
+    }
+}
+
 #[test]
 fn test_loop_detection() {
     let mut console = Console::new();
@@ -111,13 +298,13 @@ jmp -3
 acc -99
 acc +1
 jmp -4
-acc +6"));
+acc +6")).unwrap();
     console.load_program(program);
 
-    let successful = console.run_until(|_inst, meta| meta.execution_count > 0);
+    let reason = console.run_until(Revisited);
 
-    assert_eq!(5, console.accumulator);
-    assert_eq!(false, successful);
+    assert_eq!(5, console.accumulator());
+    assert_eq!(StopReason::Breakpoint(Hit::Revisited(1)), reason);
 }
 
 #[test]
@@ -131,12 +318,122 @@ jmp -3
 acc -99
 acc +1
 nop -4
-acc +6"));
+acc +6")).unwrap();
     console.load_program(program);
 
-    let successful = console.run_until(|_inst, meta| meta.execution_count > 0);
+    let reason = console.run_until(Revisited);
+
+    assert_eq!(8, console.accumulator());
+    assert_eq!(StopReason::Terminated(Terminated::Normal), reason);
+}
+
+#[test]
+fn test_solution() {
+    let input = "nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6";
+
+    assert_eq!("5", Day08.part_a(&mut std::io::Cursor::new(input)).unwrap());
+    assert_eq!("8", Day08.part_b(&mut std::io::Cursor::new(input)).unwrap());
+}
+
+#[test]
+fn test_parse_errors() {
+    let errors = ["mul +1", "acc", "acc 1x", "jmp +1 +2"].iter()
+        .map(|line| match parse_inst(line) {
+            Err(Error::Parse { column, .. }) => column,
+            other => panic!("Expected parse error, got {:?}", other),
+        })
+        .collect::<Vec<usize>>();
+
+    assert_eq!(vec![1, 4, 5, 8], errors);
 
-    assert_eq!(8, console.accumulator);
-    assert_eq!(true, successful);
+    match parse_program(std::io::Cursor::new("nop +0\nacc +1\njmp")) {
+        Err(Error::Parse { line, .. }) => assert_eq!(3, line),
+        other => panic!("Expected parse error, got {:?}", other),
+    }
 }
 
+#[test]
+fn test_step() {
+    let mut console = Console::new();
+    console.load_program(parse_program(std::io::Cursor::new("acc +3\njmp -1")).unwrap());
+
+    assert!(console.step());
+    assert_eq!((1, 3, 1), (console.pc(), console.accumulator(), console.cycles));
+    assert!(console.step());
+    assert_eq!((0, 3, 2), (console.pc(), console.accumulator(), console.cycles));
+    assert_eq!(1, console.meta().unwrap().execution_count);
+
+    console.reset();
+    assert_eq!((0, 0, 0), (console.pc(), console.accumulator(), console.cycles));
+    assert_eq!(0, console.meta().unwrap().execution_count);
+
+    console.load_program(vec![Inst::nop(0)]);
+    assert!(console.step());
+    assert!(console.is_terminated());
+    assert_eq!(None, console.inst());
+    assert!(!console.step());
+}
+
+#[test]
+fn test_extended_instructions() {
+    // Counts down from 3, writing each value, then halts before the trailing
+    // accumulate.
+    let mut console = Console::new();
+    console.load_program(parse_program(std::io::Cursor::new("set a +3
+out a
+add a -1
+jnz a -2
+halt
+acc +1")).unwrap());
+
+    assert_eq!(StopReason::Terminated(Terminated::Normal), console.run_until(|_: &Console| false));
+    assert_eq!(&[3, 2, 1], console.output());
+    assert_eq!(0, console.accumulator());
+    assert_eq!(4, console.pc());
+    assert!(!console.step());
+}
+
+#[test]
+fn test_overflow_fault() {
+    let mut console = Console::new();
+    console.load_program(parse_program(std::io::Cursor::new("acc +9223372036854775807
+acc +1")).unwrap());
+
+    assert_eq!(StopReason::Fault(Fault::Overflow), console.run_until(|_: &Console| false));
+    assert_eq!(Some(Fault::Overflow), console.fault());
+    assert_eq!((1, i64::MAX, 1), (console.pc(), console.accumulator(), console.cycles));
+}
+
+#[test]
+fn test_out_of_bounds() {
+    use breakpoint::CycleLimit;
+
+    let program = parse_program(std::io::Cursor::new("acc +1\njmp -5\nacc +10")).unwrap();
+    let run = |policy| {
+        let mut console = Console::new();
+        console.set_out_of_bounds(policy);
+        console.load_program(program.clone());
+        let reason = console.run_until(CycleLimit(5));
+
+        (reason, console.pc(), console.accumulator())
+    };
+
+    assert_eq!((StopReason::Terminated(Terminated::OutOfBounds { pc: -4 }), 1, 1), run(OutOfBounds::Trap));
+    assert_eq!((StopReason::Terminated(Terminated::Normal), 1, 1), run(OutOfBounds::Halt));
+    // -4 wraps to 2, which runs off the end normally.
+    assert_eq!((StopReason::Terminated(Terminated::Normal), 3, 11), run(OutOfBounds::Wrap));
+
+    let mut console = Console::new();
+    console.load_program(vec![Inst::jmp(i64::MAX)]);
+    assert!(console.step());
+    assert_eq!(Some(Terminated::OutOfBounds { pc: i64::MAX }), console.terminated());
+    assert!(!console.step());
+}
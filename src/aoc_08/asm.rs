@@ -0,0 +1,133 @@
+// Writing programs back out, and an assembler for a friendlier source format.
+//
+// The assembler accepts everything `parse_program` does, plus:
+//
+//     ; comments run to the end of the line
+//     loop:           ; a label names the address of the next instruction
+//         acc +1
+//         jmp loop    ; jumps can name a label instead of giving an offset
+//
+// Blank lines and indentation are ignored.
+
+use std::collections::HashMap;
+use std::io::BufRead;
+
+use crate::error::Error;
+use crate::numbered_lines;
+
+use super::isa::{parse_tokens, tokenize};
+use super::{Inst, Program};
+
+// One instruction per line in the canonical format, which `parse_program`
+// reads back unchanged.
+pub fn format_program(program: &[Inst]) -> String {
+    program.iter().map(|inst| format!("{}\n", inst)).collect()
+}
+
+// The part of a line before any comment.
+fn code(line: &str) -> &str {
+    line.find(';').map_or(line, |idx| &line[..idx])
+}
+
+// The number of leading `name:` labels in a line's tokens.
+fn label_count(tokens: &[(usize, &str)]) -> usize {
+    tokens.iter().take_while(|(_, token)| token.ends_with(':')).count()
+}
+
+fn is_label_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+pub fn assemble<T: BufRead>(input: T) -> Result<Program, Error> {
+    // Label name to its address and the line defining it.
+    let mut labels: HashMap<String, (usize, usize)> = HashMap::new();
+    let mut inst_lines = vec![];
+
+    // First pass: find every label's address, so instructions can jump
+    // forwards.
+    for line in numbered_lines(input) {
+        let (line_no, text) = line?;
+        let tokens = tokenize(code(&text));
+        let (defined, inst) = tokens.split_at(label_count(&tokens));
+
+        for (column, token) in defined {
+            let name = &token[..token.len() - 1];
+
+            if !is_label_name(name) {
+                return Err(Error::parse(*column, &text, format!("invalid label: {}", token)).on_line(line_no));
+            }
+
+            if let Some((_, first)) = labels.get(name) {
+                let reason = format!("duplicate label: {} (first defined on line {})", name, first);
+                return Err(Error::parse(*column, &text, reason).on_line(line_no));
+            }
+
+            labels.insert(name.to_string(), (inst_lines.len(), line_no));
+        }
+
+        if !inst.is_empty() {
+            inst_lines.push((line_no, text.clone()));
+        }
+    }
+
+    inst_lines.iter().enumerate().map(|(addr, (line_no, text))| {
+        let tokens = tokenize(code(text));
+        let inst = &tokens[label_count(&tokens)..];
+        let offset_of = |name: &str| labels.get(name).map(|(target, _)| *target as i64 - addr as i64);
+
+        parse_tokens(text, inst, &offset_of).map_err(|e| e.on_line(*line_no))
+    }).collect()
+}
+
+#[test]
+fn test_assemble() {
+    let source = "\
+; Adds 1 to acc until the loop is patched.
+start:
+    nop +0        ; entry
+    acc +1
+    jmp skip
+    acc +3
+back: jmp -3
+skip:
+    acc -99
+    acc +1
+    jmp back
+end: acc +6
+";
+
+    let expected = super::parse_program(std::io::Cursor::new("nop +0
+acc +1
+jmp +3
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -3
+acc +6")).unwrap();
+
+    assert_eq!(expected, assemble(std::io::Cursor::new(source)).unwrap());
+}
+
+#[test]
+fn test_round_trip() {
+    let source = "set a +3\nout a\nadd a -1\njnz a -2\nhalt\nacc -7\n";
+    let program = assemble(std::io::Cursor::new(source)).unwrap();
+
+    assert_eq!(source, format_program(&program));
+    assert_eq!(program, super::parse_program(std::io::Cursor::new(format_program(&program))).unwrap());
+}
+
+#[test]
+fn test_assemble_errors() {
+    let location = |source: &str| match assemble(std::io::Cursor::new(source)) {
+        Err(Error::Parse { line, column, .. }) => (line, column),
+        other => panic!("Expected parse error, got {:?}", other),
+    };
+
+    assert_eq!((2, 9), location("a: nop +0\n    jmp b ; b is undefined"));
+    assert_eq!((3, 1), location("a: nop +0\n\na:\nhalt"));
+    assert_eq!((1, 1), location("1x: halt"));
+    assert_eq!((1, 4), location("a: mul +1"));
+}
@@ -0,0 +1,183 @@
+// Conditions for `Console::run_until` to stop at. Each is checked before
+// every instruction executes, and closures taking the console work as
+// breakpoints too.
+
+use super::Console;
+
+// Which breakpoint fired.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Hit {
+    CycleLimit(usize),
+    PcEquals(usize),
+    // The accumulator value which matched.
+    Accumulator(i64),
+    // The address about to execute for the second time.
+    Revisited(usize),
+    // A closure returned true.
+    Custom,
+    Both(Box<Hit>, Box<Hit>),
+}
+
+pub trait Breakpoint {
+    fn check(&mut self, console: &Console) -> Option<Hit>;
+
+    // Fires when both breakpoints would fire.
+    fn and<B: Breakpoint>(self, other: B) -> And<Self, B> where Self: Sized {
+        And(self, other)
+    }
+
+    // Fires when either breakpoint would fire, reporting `self` if both do.
+    fn or<B: Breakpoint>(self, other: B) -> Or<Self, B> where Self: Sized {
+        Or(self, other)
+    }
+}
+
+impl<F: FnMut(&Console) -> bool> Breakpoint for F {
+    fn check(&mut self, console: &Console) -> Option<Hit> {
+        if self(console) {
+            Some(Hit::Custom)
+        } else {
+            None
+        }
+    }
+}
+
+// Stops once this many instructions have executed.
+pub struct CycleLimit(pub usize);
+
+impl Breakpoint for CycleLimit {
+    fn check(&mut self, console: &Console) -> Option<Hit> {
+        if console.cycles >= self.0 {
+            Some(Hit::CycleLimit(self.0))
+        } else {
+            None
+        }
+    }
+}
+
+pub struct PcEquals(pub usize);
+
+impl Breakpoint for PcEquals {
+    fn check(&mut self, console: &Console) -> Option<Hit> {
+        if console.pc() == self.0 {
+            Some(Hit::PcEquals(self.0))
+        } else {
+            None
+        }
+    }
+}
+
+// Stops when the accumulator matches a predicate, e.g.
+// `AccMatches(|acc| acc > 100)`.
+pub struct AccMatches<F>(pub F);
+
+impl<F: FnMut(i64) -> bool> Breakpoint for AccMatches<F> {
+    fn check(&mut self, console: &Console) -> Option<Hit> {
+        let acc = console.accumulator();
+
+        if (self.0)(acc) {
+            Some(Hit::Accumulator(acc))
+        } else {
+            None
+        }
+    }
+}
+
+// Stops before any instruction executes a second time.
+pub struct Revisited;
+
+impl Breakpoint for Revisited {
+    fn check(&mut self, console: &Console) -> Option<Hit> {
+        match console.meta() {
+            Some(meta) if meta.execution_count > 0 => Some(Hit::Revisited(console.pc())),
+            _ => None,
+        }
+    }
+}
+
+pub struct And<A, B>(A, B);
+
+impl<A: Breakpoint, B: Breakpoint> Breakpoint for And<A, B> {
+    fn check(&mut self, console: &Console) -> Option<Hit> {
+        // Both sides are always checked, so stateful breakpoints see every
+        // instruction.
+        match (self.0.check(console), self.1.check(console)) {
+            (Some(a), Some(b)) => Some(Hit::Both(Box::new(a), Box::new(b))),
+            _ => None,
+        }
+    }
+}
+
+pub struct Or<A, B>(A, B);
+
+impl<A: Breakpoint, B: Breakpoint> Breakpoint for Or<A, B> {
+    fn check(&mut self, console: &Console) -> Option<Hit> {
+        let a = self.0.check(console);
+        let b = self.1.check(console);
+
+        a.or(b)
+    }
+}
+
+#[cfg(test)]
+fn example_console() -> Console {
+    let mut console = Console::new();
+    console.load_program(super::parse_program(std::io::Cursor::new("nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6")).unwrap());
+    console
+}
+
+#[test]
+fn test_breakpoints() {
+    use super::StopReason;
+
+    let mut console = example_console();
+    assert_eq!(StopReason::Breakpoint(Hit::Revisited(1)), console.run_until(Revisited));
+    assert_eq!(5, console.accumulator());
+
+    let mut console = example_console();
+    assert_eq!(StopReason::Breakpoint(Hit::CycleLimit(3)), console.run_until(CycleLimit(3)));
+    assert_eq!(6, console.pc());
+
+    let mut console = example_console();
+    assert_eq!(StopReason::Breakpoint(Hit::Accumulator(2)), console.run_until(AccMatches(|acc| acc > 1)));
+
+    let mut console = example_console();
+    let mut seen = 0;
+    let reason = console.run_until(move |_: &Console| {
+        seen += 1;
+        seen > 4
+    });
+    assert_eq!(StopReason::Breakpoint(Hit::Custom), reason);
+    assert_eq!(4, console.cycles);
+}
+
+#[test]
+fn test_combined_breakpoints() {
+    use super::{StopReason, Terminated};
+
+    // The second visit to address 4 is never reached, as 1 is revisited
+    // first.
+    let mut console = example_console();
+    assert_eq!(
+        StopReason::Breakpoint(Hit::Revisited(1)),
+        console.run_until(PcEquals(4).and(AccMatches(|acc| acc > 5)).or(Revisited))
+    );
+
+    let mut console = example_console();
+    assert_eq!(
+        StopReason::Breakpoint(Hit::Both(Box::new(Hit::PcEquals(4)), Box::new(Hit::Accumulator(5)))),
+        console.run_until(PcEquals(4).and(AccMatches(|acc| acc >= 5)))
+    );
+
+    let mut console = example_console();
+    console.load_program(super::parse_program(std::io::Cursor::new("acc +1\nnop +0")).unwrap());
+    assert_eq!(StopReason::Terminated(Terminated::Normal), console.run_until(PcEquals(7).or(CycleLimit(100))));
+}
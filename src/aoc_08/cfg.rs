@@ -0,0 +1,445 @@
+// Control-flow graph of basic blocks, and static checks built on it.
+
+use std::fmt;
+use std::ops::Range;
+
+use super::isa::Control;
+use super::{Inst, Program};
+
+pub type BlockId = usize;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Successor {
+    Block(BlockId),
+    // Execution halts or steps to exactly `program.len()`, terminating
+    // normally.
+    Exit,
+    // Execution jumps to an address outside the program.
+    OutOfBounds(i64),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct BasicBlock {
+    pub addrs: Range<usize>,
+    pub successors: Vec<Successor>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Cfg {
+    pub blocks: Vec<BasicBlock>,
+    // The block containing each instruction.
+    block_of: Vec<BlockId>,
+}
+
+// Addresses control may pass to after executing `inst` at `addr`, with `halt`
+// treated as stepping to the end of a program of length `len`.
+fn targets(addr: usize, inst: &Inst, len: usize) -> Vec<i64> {
+    let next = addr as i64 + 1;
+    let jump = || (addr as i64).saturating_add(inst.jump_offset().unwrap());
+
+    match inst.spec().control {
+        Control::Next => vec![next],
+        Control::Jump(_) => vec![jump()],
+        Control::Branch(_) => vec![next, jump()],
+        Control::Halt => vec![len as i64],
+    }
+}
+
+// Whether `inst` ends a basic block.
+fn is_branch(inst: &Inst) -> bool {
+    inst.spec().control != Control::Next
+}
+
+impl Cfg {
+    pub fn build(program: &Program) -> Cfg {
+        let len = program.len();
+
+        // A block starts at the entry, at every jump target and after every
+        // jump.
+        let mut leaders = vec![false; len];
+        if len > 0 {
+            leaders[0] = true;
+        }
+
+        for (addr, inst) in program.iter().enumerate() {
+            if is_branch(inst) {
+                for target in targets(addr, inst, len) {
+                    if (0..len as i64).contains(&target) {
+                        leaders[target as usize] = true;
+                    }
+                }
+
+                if addr + 1 < len {
+                    leaders[addr + 1] = true;
+                }
+            }
+        }
+
+        let mut block_of = vec![0; len];
+        let mut starts = vec![];
+        for addr in 0..len {
+            if leaders[addr] {
+                starts.push(addr);
+            }
+            block_of[addr] = starts.len() - 1;
+        }
+
+        let blocks = starts.iter().enumerate().map(|(id, start)| {
+            let end = starts.get(id + 1).copied().unwrap_or(len);
+            let last = end - 1;
+
+            let successors = targets(last, &program[last], len).into_iter()
+                .map(|target| {
+                    if target == len as i64 {
+                        Successor::Exit
+                    } else if (0..len as i64).contains(&target) {
+                        Successor::Block(block_of[target as usize])
+                    } else {
+                        Successor::OutOfBounds(target)
+                    }
+                })
+                .collect();
+
+            BasicBlock { addrs: *start..end, successors }
+        }).collect();
+
+        Cfg { blocks, block_of }
+    }
+
+    pub fn block_at(&self, addr: usize) -> Option<BlockId> {
+        self.block_of.get(addr).copied()
+    }
+
+    fn block_successors(&self, id: BlockId) -> impl Iterator<Item=BlockId> + '_ {
+        self.blocks[id].successors.iter().filter_map(|s| match s {
+            Successor::Block(next) => Some(*next),
+            _ => None,
+        })
+    }
+
+    // Which blocks can be reached from the entry point.
+    pub fn reachable(&self) -> Vec<bool> {
+        let mut seen = vec![false; self.blocks.len()];
+        let mut frontier = vec![];
+
+        if !self.blocks.is_empty() {
+            seen[0] = true;
+            frontier.push(0);
+        }
+
+        while let Some(id) = frontier.pop() {
+            for next in self.block_successors(id) {
+                if !seen[next] {
+                    seen[next] = true;
+                    frontier.push(next);
+                }
+            }
+        }
+
+        seen
+    }
+
+    // Strongly connected components of the block graph, using an iterative
+    // form of Tarjan's algorithm so long programs don't overflow the stack.
+    fn components(&self) -> Vec<Vec<BlockId>> {
+        const UNVISITED: usize = usize::MAX;
+
+        let count = self.blocks.len();
+        let mut index = vec![UNVISITED; count];
+        let mut low_link = vec![0; count];
+        let mut on_stack = vec![false; count];
+        let mut stack = vec![];
+        let mut next_index = 0;
+        let mut components = vec![];
+
+        for root in 0..count {
+            if index[root] != UNVISITED {
+                continue;
+            }
+
+            let mut work: Vec<(BlockId, Vec<BlockId>)> = vec![];
+            index[root] = next_index;
+            low_link[root] = next_index;
+            next_index += 1;
+            stack.push(root);
+            on_stack[root] = true;
+            work.push((root, self.block_successors(root).collect()));
+
+            while let Some((node, mut pending)) = work.pop() {
+                if let Some(next) = pending.pop() {
+                    work.push((node, pending));
+
+                    if index[next] == UNVISITED {
+                        index[next] = next_index;
+                        low_link[next] = next_index;
+                        next_index += 1;
+                        stack.push(next);
+                        on_stack[next] = true;
+                        work.push((next, self.block_successors(next).collect()));
+                    } else if on_stack[next] {
+                        low_link[node] = low_link[node].min(index[next]);
+                    }
+
+                    continue;
+                }
+
+                if let Some((parent, _)) = work.last() {
+                    let parent = *parent;
+                    low_link[parent] = low_link[parent].min(low_link[node]);
+                }
+
+                if low_link[node] == index[node] {
+                    let mut component = vec![];
+
+                    loop {
+                        let member = stack.pop().unwrap();
+                        on_stack[member] = false;
+                        component.push(member);
+
+                        if member == node {
+                            break;
+                        }
+                    }
+
+                    component.sort_unstable();
+                    components.push(component);
+                }
+            }
+        }
+
+        components
+    }
+
+    // Cycles of blocks with no edge leading anywhere else: once execution
+    // enters one, it can never leave.
+    pub fn closed_loops(&self) -> Vec<Vec<BlockId>> {
+        let components = self.components();
+
+        let mut component_of = vec![0; self.blocks.len()];
+        for (idx, component) in components.iter().enumerate() {
+            for id in component {
+                component_of[*id] = idx;
+            }
+        }
+
+        let mut loops: Vec<Vec<BlockId>> = components.into_iter()
+            .filter(|component| {
+                let is_cycle = component.len() > 1 ||
+                    self.block_successors(component[0]).any(|next| next == component[0]);
+
+                is_cycle && component.iter().all(|id|
+                    self.blocks[*id].successors.iter().all(|s| match s {
+                        Successor::Block(next) => component_of[*next] == component_of[*id],
+                        _ => false,
+                    }))
+            })
+            .collect();
+
+        loops.sort();
+        loops
+    }
+
+    // Renders the graph in Graphviz DOT format. Unreachable blocks are drawn
+    // dashed and blocks in closed loops are drawn in red.
+    pub fn to_dot(&self, program: &Program) -> String {
+        let reachable = self.reachable();
+        let mut looping = vec![false; self.blocks.len()];
+        for id in self.closed_loops().into_iter().flatten() {
+            looping[id] = true;
+        }
+
+        let mut out = String::from("digraph program {\n");
+        out.push_str("    node [shape=box, fontname=\"monospace\"];\n");
+        out.push_str("    entry [shape=point];\n");
+        out.push_str("    exit [shape=doublecircle, label=\"exit\"];\n");
+
+        if !self.blocks.is_empty() {
+            out.push_str("    entry -> b0;\n");
+        }
+
+        for (id, block) in self.blocks.iter().enumerate() {
+            let label: String = block.addrs.clone()
+                .map(|addr| format!("{:04}: {}\\l", addr, program[addr]))
+                .collect();
+
+            let mut attrs = format!("label=\"{}\"", label.replace('"', "\\\""));
+            if !reachable[id] {
+                attrs.push_str(", style=dashed, color=gray");
+            }
+            if looping[id] {
+                attrs.push_str(", color=red");
+            }
+
+            out.push_str(&format!("    b{} [{}];\n", id, attrs));
+
+            for successor in &block.successors {
+                match successor {
+                    Successor::Block(next) => out.push_str(&format!("    b{} -> b{};\n", id, next)),
+                    Successor::Exit => out.push_str(&format!("    b{} -> exit;\n", id)),
+                    Successor::OutOfBounds(target) => {
+                        out.push_str(&format!("    oob{} [shape=octagon, color=red, label=\"out of bounds: {}\"];\n", id, target));
+                        out.push_str(&format!("    b{} -> oob{};\n", id, id));
+                    }
+                }
+            }
+        }
+
+        out.push_str("}\n");
+        out
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct OutOfBoundsJump {
+    pub addr: usize,
+    pub target: i64,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Report {
+    pub instructions: usize,
+    pub blocks: usize,
+    // Runs of instructions which can never execute.
+    pub unreachable: Vec<Range<usize>>,
+    // Address ranges of each reachable closed loop, one entry per loop.
+    pub infinite_loops: Vec<Vec<Range<usize>>>,
+    pub out_of_bounds: Vec<OutOfBoundsJump>,
+}
+
+impl Report {
+    pub fn is_clean(&self) -> bool {
+        self.unreachable.is_empty() && self.infinite_loops.is_empty() && self.out_of_bounds.is_empty()
+    }
+}
+
+pub fn analyse(program: &Program) -> Report {
+    let cfg = Cfg::build(program);
+    let reachable = cfg.reachable();
+
+    let mut unreachable: Vec<Range<usize>> = vec![];
+    for (id, block) in cfg.blocks.iter().enumerate() {
+        if reachable[id] {
+            continue;
+        }
+
+        match unreachable.last_mut() {
+            Some(range) if range.end == block.addrs.start => range.end = block.addrs.end,
+            _ => unreachable.push(block.addrs.clone()),
+        }
+    }
+
+    let infinite_loops = cfg.closed_loops().into_iter()
+        .filter(|blocks| reachable[blocks[0]])
+        .map(|blocks| blocks.iter().map(|id| cfg.blocks[*id].addrs.clone()).collect())
+        .collect();
+
+    let out_of_bounds = program.iter().enumerate()
+        .flat_map(|(addr, inst)| targets(addr, inst, program.len()).into_iter()
+            .filter(|target| !(0..=program.len() as i64).contains(target))
+            .map(move |target| OutOfBoundsJump { addr, target }))
+        .collect();
+
+    Report {
+        instructions: program.len(),
+        blocks: cfg.blocks.len(),
+        unreachable,
+        infinite_loops,
+        out_of_bounds,
+    }
+}
+
+fn format_ranges(ranges: &[Range<usize>]) -> String {
+    ranges.iter()
+        .map(|r| if r.len() == 1 { format!("{}", r.start) } else { format!("{}-{}", r.start, r.end - 1) })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} instructions in {} basic blocks.", self.instructions, self.blocks)?;
+
+        if self.is_clean() {
+            return writeln!(f, "No problems found.");
+        }
+
+        if !self.unreachable.is_empty() {
+            writeln!(f, "Unreachable instructions: {}", format_ranges(&self.unreachable))?;
+        }
+
+        for ranges in &self.infinite_loops {
+            writeln!(f, "Infinite loop through instructions: {}", format_ranges(ranges))?;
+        }
+
+        for jump in &self.out_of_bounds {
+            writeln!(f, "Jump out of bounds at {}: target {}", jump.addr, jump.target)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+fn example_program() -> Program {
+    super::parse_program(std::io::Cursor::new("nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6")).unwrap()
+}
+
+#[test]
+fn test_build_cfg() {
+    let cfg = Cfg::build(&example_program());
+
+    assert_eq!(
+        vec![
+            BasicBlock { addrs: 0..1, successors: vec![Successor::Block(1)] },
+            BasicBlock { addrs: 1..3, successors: vec![Successor::Block(4)] },
+            BasicBlock { addrs: 3..5, successors: vec![Successor::Block(1)] },
+            BasicBlock { addrs: 5..6, successors: vec![Successor::Block(4)] },
+            BasicBlock { addrs: 6..8, successors: vec![Successor::Block(2)] },
+            BasicBlock { addrs: 8..9, successors: vec![Successor::Exit] },
+        ],
+        cfg.blocks
+    );
+    assert_eq!(Some(4), cfg.block_at(7));
+}
+
+#[test]
+fn test_analyse() {
+    let report = analyse(&example_program());
+
+    assert_eq!(vec![5..6, 8..9], report.unreachable);
+    assert_eq!(vec![vec![1..3, 3..5, 6..8]], report.infinite_loops);
+    assert!(report.out_of_bounds.is_empty());
+
+    let mut fixed = example_program();
+    fixed[7] = Inst::nop(-4);
+    fixed[5] = Inst::jmp(-10);
+
+    let report = analyse(&fixed);
+
+    assert!(report.infinite_loops.is_empty());
+    assert_eq!(vec![OutOfBoundsJump { addr: 5, target: -5 }], report.out_of_bounds);
+    assert_eq!(
+        "9 instructions in 5 basic blocks.\n\
+         Unreachable instructions: 3-5\n\
+         Jump out of bounds at 5: target -5\n",
+        report.to_string()
+    );
+}
+
+#[test]
+fn test_to_dot() {
+    let dot = Cfg::build(&example_program()).to_dot(&example_program());
+
+    assert!(dot.starts_with("digraph program {\n"));
+    assert!(dot.contains("    b1 [label=\"0001: acc +1\\l0002: jmp +4\\l\", color=red];\n"));
+    assert!(dot.contains("    b3 [label=\"0005: acc -99\\l\", style=dashed, color=gray];\n"));
+    assert!(dot.contains("    b5 -> exit;\n"));
+}
@@ -0,0 +1,557 @@
+// The console's instruction set. Every instruction is described by a row in
+// `INSTRUCTIONS`, which the parser, the interpreter and the static analyses
+// all read from, so adding an instruction means adding a row here.
+
+use std::fmt;
+
+use crate::error::Error;
+
+pub const REGISTERS: [&str; 5] = ["acc", "a", "b", "c", "d"];
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Reg(u8);
+
+impl Reg {
+    pub const ACC: Reg = Reg(0);
+
+    pub fn parse(input: &str) -> Option<Reg> {
+        REGISTERS.iter().position(|name| *name == input).map(|idx| Reg(idx as u8))
+    }
+
+    pub fn name(self) -> &'static str {
+        REGISTERS[self.0 as usize]
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Operand {
+    Imm(i64),
+    Reg(Reg),
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum OperandKind {
+    // A signed constant, written `+5` or `-3`.
+    Imm,
+    // A jump, relative to the instruction's own address.
+    Offset,
+    // A register to write to.
+    Reg,
+    // A register or a constant to read from.
+    Src,
+}
+
+// How an instruction affects control flow, for static analysis.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Control {
+    // Always continues with the next instruction.
+    Next,
+    // Always jumps by the offset in the given operand.
+    Jump(usize),
+    // Either continues with the next instruction or jumps by the offset in
+    // the given operand, depending on register values.
+    Branch(usize),
+    // Stops the program.
+    Halt,
+}
+
+// What the interpreter should do after executing an instruction.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Flow {
+    Next,
+    Jump(i64),
+    Halt,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Fault {
+    // Arithmetic overflowed a 64 bit register.
+    Overflow,
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fault::Overflow => write!(f, "arithmetic overflow"),
+        }
+    }
+}
+
+// The state instructions operate on.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Cpu {
+    pub registers: [i64; REGISTERS.len()],
+    pub output: Vec<i64>,
+}
+
+impl Cpu {
+    pub fn get(&self, reg: Reg) -> i64 {
+        self.registers[reg.0 as usize]
+    }
+
+    pub fn set(&mut self, reg: Reg, value: i64) {
+        self.registers[reg.0 as usize] = value;
+    }
+
+    pub fn value(&self, operand: Operand) -> i64 {
+        match operand {
+            Operand::Imm(value) => value,
+            Operand::Reg(reg) => self.get(reg),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Op {
+    Nop,
+    Acc,
+    Jmp,
+    Halt,
+    Out,
+    Set,
+    Add,
+    Eq,
+    Lt,
+    Jz,
+    Jnz,
+}
+
+pub struct InstSpec {
+    pub op: Op,
+    pub mnemonic: &'static str,
+    pub operands: &'static [OperandKind],
+    pub control: Control,
+    // The operand naming the register this instruction writes, if any.
+    pub dest: Option<usize>,
+    pub exec: fn(&mut Cpu, &[Operand]) -> Result<Flow, Fault>,
+}
+
+fn reg(operand: Operand) -> Reg {
+    match operand {
+        Operand::Reg(reg) => reg,
+        Operand::Imm(_) => panic!("Operand kinds are checked by Inst::new."),
+    }
+}
+
+fn imm(operand: Operand) -> i64 {
+    match operand {
+        Operand::Imm(value) => value,
+        Operand::Reg(_) => panic!("Operand kinds are checked by Inst::new."),
+    }
+}
+
+// Indexed by `Op`.
+pub static INSTRUCTIONS: [InstSpec; 11] = [
+    InstSpec {
+        op: Op::Nop,
+        mnemonic: "nop",
+        operands: &[OperandKind::Imm],
+        control: Control::Next,
+        dest: None,
+        exec: |_cpu, _args| Ok(Flow::Next),
+    },
+    InstSpec {
+        op: Op::Acc,
+        mnemonic: "acc",
+        operands: &[OperandKind::Imm],
+        control: Control::Next,
+        dest: None,
+        exec: |cpu, args| {
+            let value = cpu.get(Reg::ACC).checked_add(imm(args[0])).ok_or(Fault::Overflow)?;
+            cpu.set(Reg::ACC, value);
+            Ok(Flow::Next)
+        },
+    },
+    InstSpec {
+        op: Op::Jmp,
+        mnemonic: "jmp",
+        operands: &[OperandKind::Offset],
+        control: Control::Jump(0),
+        dest: None,
+        exec: |_cpu, args| Ok(Flow::Jump(imm(args[0]))),
+    },
+    InstSpec {
+        op: Op::Halt,
+        mnemonic: "halt",
+        operands: &[],
+        control: Control::Halt,
+        dest: None,
+        exec: |_cpu, _args| Ok(Flow::Halt),
+    },
+    InstSpec {
+        op: Op::Out,
+        mnemonic: "out",
+        operands: &[OperandKind::Src],
+        control: Control::Next,
+        dest: None,
+        exec: |cpu, args| {
+            let value = cpu.value(args[0]);
+            cpu.output.push(value);
+            Ok(Flow::Next)
+        },
+    },
+    InstSpec {
+        op: Op::Set,
+        mnemonic: "set",
+        operands: &[OperandKind::Reg, OperandKind::Src],
+        control: Control::Next,
+        dest: Some(0),
+        exec: |cpu, args| {
+            let value = cpu.value(args[1]);
+            cpu.set(reg(args[0]), value);
+            Ok(Flow::Next)
+        },
+    },
+    InstSpec {
+        op: Op::Add,
+        mnemonic: "add",
+        operands: &[OperandKind::Reg, OperandKind::Src],
+        control: Control::Next,
+        dest: Some(0),
+        exec: |cpu, args| {
+            let value = cpu.get(reg(args[0])).checked_add(cpu.value(args[1])).ok_or(Fault::Overflow)?;
+            cpu.set(reg(args[0]), value);
+            Ok(Flow::Next)
+        },
+    },
+    InstSpec {
+        op: Op::Eq,
+        mnemonic: "eq",
+        operands: &[OperandKind::Reg, OperandKind::Src, OperandKind::Src],
+        control: Control::Next,
+        dest: Some(0),
+        exec: |cpu, args| {
+            let value = cpu.value(args[1]) == cpu.value(args[2]);
+            cpu.set(reg(args[0]), value as i64);
+            Ok(Flow::Next)
+        },
+    },
+    InstSpec {
+        op: Op::Lt,
+        mnemonic: "lt",
+        operands: &[OperandKind::Reg, OperandKind::Src, OperandKind::Src],
+        control: Control::Next,
+        dest: Some(0),
+        exec: |cpu, args| {
+            let value = cpu.value(args[1]) < cpu.value(args[2]);
+            cpu.set(reg(args[0]), value as i64);
+            Ok(Flow::Next)
+        },
+    },
+    InstSpec {
+        op: Op::Jz,
+        mnemonic: "jz",
+        operands: &[OperandKind::Src, OperandKind::Offset],
+        control: Control::Branch(1),
+        dest: None,
+        exec: |cpu, args| Ok(if cpu.value(args[0]) == 0 { Flow::Jump(imm(args[1])) } else { Flow::Next }),
+    },
+    InstSpec {
+        op: Op::Jnz,
+        mnemonic: "jnz",
+        operands: &[OperandKind::Src, OperandKind::Offset],
+        control: Control::Branch(1),
+        dest: None,
+        exec: |cpu, args| Ok(if cpu.value(args[0]) != 0 { Flow::Jump(imm(args[1])) } else { Flow::Next }),
+    },
+];
+
+impl Op {
+    pub fn spec(self) -> &'static InstSpec {
+        &INSTRUCTIONS[self as usize]
+    }
+
+    pub fn from_mnemonic(mnemonic: &str) -> Option<Op> {
+        INSTRUCTIONS.iter().find(|spec| spec.mnemonic == mnemonic).map(|spec| spec.op)
+    }
+}
+
+impl OperandKind {
+    fn accepts(self, operand: Operand) -> bool {
+        matches!(
+            (self, operand),
+            (OperandKind::Imm, Operand::Imm(_)) | (OperandKind::Offset, Operand::Imm(_))
+                | (OperandKind::Reg, Operand::Reg(_)) | (OperandKind::Src, _)
+        )
+    }
+}
+
+// Why `Inst::new` rejected its operands.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum OperandError {
+    Count { op: Op, expected: usize, found: usize },
+    // The operand at `idx`, counting from 0, is a register where `expected`
+    // needs a constant, or the other way round.
+    Kind { op: Op, idx: usize, expected: OperandKind },
+}
+
+impl fmt::Display for OperandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OperandError::Count { op, expected, found } =>
+                write!(f, "{} takes {} operand(s), not {}", op.spec().mnemonic, expected, found),
+            OperandError::Kind { op, idx, expected } => {
+                let kind = match expected {
+                    OperandKind::Imm | OperandKind::Offset => "a constant",
+                    OperandKind::Reg => "a register",
+                    OperandKind::Src => "a register or a constant",
+                };
+                write!(f, "operand {} of {} must be {}", idx + 1, op.spec().mnemonic, kind)
+            }
+        }
+    }
+}
+
+impl std::error::Error for OperandError {}
+
+// An instruction whose operands match its `InstSpec`, which the interpreter
+// and analyses rely on.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Inst {
+    op: Op,
+    args: Vec<Operand>,
+}
+
+impl Inst {
+    pub fn new(op: Op, args: Vec<Operand>) -> Result<Inst, OperandError> {
+        let kinds = op.spec().operands;
+
+        if args.len() != kinds.len() {
+            return Err(OperandError::Count { op, expected: kinds.len(), found: args.len() });
+        }
+
+        if let Some(idx) = kinds.iter().zip(&args).position(|(kind, arg)| !kind.accepts(*arg)) {
+            return Err(OperandError::Kind { op, idx, expected: kinds[idx] });
+        }
+
+        Ok(Inst { op, args })
+    }
+
+    pub fn nop(value: i64) -> Inst {
+        Inst { op: Op::Nop, args: vec![Operand::Imm(value)] }
+    }
+
+    pub fn acc(value: i64) -> Inst {
+        Inst { op: Op::Acc, args: vec![Operand::Imm(value)] }
+    }
+
+    pub fn jmp(offset: i64) -> Inst {
+        Inst { op: Op::Jmp, args: vec![Operand::Imm(offset)] }
+    }
+
+    pub fn op(&self) -> Op {
+        self.op
+    }
+
+    pub fn args(&self) -> &[Operand] {
+        &self.args
+    }
+
+    pub fn spec(&self) -> &'static InstSpec {
+        self.op.spec()
+    }
+
+    pub fn execute(&self, cpu: &mut Cpu) -> Result<Flow, Fault> {
+        (self.spec().exec)(cpu, &self.args)
+    }
+
+    // The offset of a jump or branch instruction.
+    pub fn jump_offset(&self) -> Option<i64> {
+        match self.spec().control {
+            Control::Jump(idx) | Control::Branch(idx) => Some(imm(self.args[idx])),
+            Control::Next | Control::Halt => None,
+        }
+    }
+
+    pub fn writes(&self) -> Option<Reg> {
+        self.spec().dest.map(|idx| reg(self.args[idx]))
+    }
+}
+
+// Written the way the enum-based instructions used to print, e.g. `Jmp(-3)`
+// or `Eq(a, acc, 5)`.
+impl fmt::Debug for Inst {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.op)?;
+
+        if !self.args.is_empty() {
+            let args: Vec<String> = self.args.iter().map(|arg| match arg {
+                Operand::Imm(value) => value.to_string(),
+                Operand::Reg(reg) => reg.name().to_string(),
+            }).collect();
+
+            write!(f, "({})", args.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+// Written in the canonical source format, e.g. `jmp -3` or `eq a acc +5`.
+impl fmt::Display for Inst {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.spec().mnemonic)?;
+
+        for arg in &self.args {
+            match arg {
+                Operand::Imm(value) => write!(f, " {:+}", value)?,
+                Operand::Reg(reg) => write!(f, " {}", reg.name())?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Resolves a label used as a jump operand to an offset from the instruction.
+pub type Labels<'a> = &'a dyn Fn(&str) -> Option<i64>;
+
+fn parse_operand(kind: OperandKind, token: &str, column: usize, line: &str, labels: Labels) -> Result<Operand, Error> {
+    let is_name = token.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_');
+
+    let parse_imm = || token.parse::<i64>()
+        .map(Operand::Imm)
+        .map_err(|e| Error::parse(column, line, format!("invalid operand: {}", e)));
+
+    let parse_reg = || Reg::parse(token)
+        .map(Operand::Reg)
+        .ok_or_else(|| Error::parse(column, line, format!("unknown register: {}", token)));
+
+    match kind {
+        OperandKind::Offset if is_name => labels(token)
+            .map(Operand::Imm)
+            .ok_or_else(|| Error::parse(column, line, format!("undefined label: {}", token))),
+        OperandKind::Imm | OperandKind::Offset => parse_imm(),
+        OperandKind::Reg => parse_reg(),
+        OperandKind::Src if is_name => parse_reg(),
+        OperandKind::Src => parse_imm(),
+    }
+}
+
+// Splits a line into whitespace-separated tokens, with their 1-indexed
+// columns.
+pub(crate) fn tokenize(line: &str) -> Vec<(usize, &str)> {
+    let mut tokens = vec![];
+    let mut start = None;
+
+    for (idx, c) in line.char_indices().chain(std::iter::once((line.len(), ' '))) {
+        match (start, c.is_whitespace()) {
+            (None, false) => start = Some(idx),
+            (Some(s), true) => {
+                tokens.push((s + 1, &line[s..idx]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+
+    tokens
+}
+
+pub fn parse_inst(line: &str) -> Result<Inst, Error> {
+    parse_tokens(line, &tokenize(line), &|_| None)
+}
+
+// Parses an instruction from tokens of `line`, which errors are reported
+// against.
+pub(crate) fn parse_tokens(line: &str, tokens: &[(usize, &str)], labels: Labels) -> Result<Inst, Error> {
+    let (column, mnemonic) = match tokens.first() {
+        Some(token) => *token,
+        None => return Err(Error::parse(1, line, "expected an instruction")),
+    };
+
+    let spec = Op::from_mnemonic(mnemonic)
+        .ok_or_else(|| Error::parse(column, line, format!("unexpected instruction: {}", mnemonic)))?
+        .spec();
+
+    let operands = &tokens[1..];
+
+    if let Some((column, _)) = operands.get(spec.operands.len()) {
+        return Err(Error::parse(*column, line, "unexpected trailing input"));
+    }
+
+    if operands.len() < spec.operands.len() {
+        let (column, token) = tokens.last().unwrap();
+        return Err(Error::parse(column + token.len(), line, "missing operand"));
+    }
+
+    let args = spec.operands.iter().zip(operands)
+        .map(|(kind, (column, token))| parse_operand(*kind, token, *column, line, labels))
+        .collect::<Result<Vec<Operand>, Error>>()?;
+
+    // `parse_operand` only returns operands of the kind asked for.
+    Ok(Inst { op: spec.op, args })
+}
+
+#[test]
+fn test_instruction_table_order() {
+    for (idx, spec) in INSTRUCTIONS.iter().enumerate() {
+        assert_eq!(idx, spec.op as usize, "{} is out of place", spec.mnemonic);
+        assert_eq!(Some(spec.op), Op::from_mnemonic(spec.mnemonic));
+    }
+}
+
+#[test]
+fn test_parse_inst() {
+    let a = Reg::parse("a").unwrap();
+
+    assert_eq!(Inst::nop(0), parse_inst("nop +0").unwrap());
+    assert_eq!(Inst::acc(-99), parse_inst("acc -99").unwrap());
+    assert_eq!(Inst::new(Op::Halt, vec![]).unwrap(), parse_inst("halt").unwrap());
+    assert_eq!(
+        Inst::new(Op::Eq, vec![Operand::Reg(a), Operand::Reg(Reg::ACC), Operand::Imm(5)]).unwrap(),
+        parse_inst("eq a acc +5").unwrap()
+    );
+    assert_eq!(
+        Inst::new(Op::Jnz, vec![Operand::Reg(a), Operand::Imm(-2)]).unwrap(),
+        parse_inst("jnz  a -2").unwrap()
+    );
+    assert_eq!(Inst::acc(i64::MAX), parse_inst("acc +9223372036854775807").unwrap());
+
+    assert!(parse_inst("acc +9223372036854775808").is_err());
+    assert!(parse_inst("set +1 a").is_err());
+    assert!(parse_inst("out x").is_err());
+    assert!(parse_inst("").is_err());
+    assert!(parse_inst("jmp loop").is_err());
+}
+
+#[test]
+fn test_new_inst() {
+    let a = Reg::parse("a").unwrap();
+
+    assert_eq!(Err(OperandError::Count { op: Op::Jmp, expected: 1, found: 0 }), Inst::new(Op::Jmp, vec![]));
+    assert_eq!(
+        Err(OperandError::Kind { op: Op::Set, idx: 0, expected: OperandKind::Reg }),
+        Inst::new(Op::Set, vec![Operand::Imm(1), Operand::Imm(2)])
+    );
+    assert_eq!(
+        "operand 2 of jz must be a constant",
+        Inst::new(Op::Jz, vec![Operand::Imm(0), Operand::Reg(a)]).unwrap_err().to_string()
+    );
+    assert_eq!(Ok(Inst::acc(3)), Inst::new(Op::Acc, vec![Operand::Imm(3)]));
+}
+
+#[test]
+fn test_display_inst() {
+    for line in &["nop +0", "acc -99", "jmp +4", "halt", "eq a acc +5", "jnz b -2", "out c"] {
+        assert_eq!(*line, parse_inst(line).unwrap().to_string());
+    }
+}
+
+#[test]
+fn test_execute() {
+    let mut cpu = Cpu::default();
+    let a = Reg::parse("a").unwrap();
+
+    assert_eq!(Ok(Flow::Next), parse_inst("set a +7").unwrap().execute(&mut cpu));
+    assert_eq!(Ok(Flow::Next), parse_inst("lt acc acc a").unwrap().execute(&mut cpu));
+    assert_eq!(1, cpu.get(Reg::ACC));
+    assert_eq!(Ok(Flow::Jump(3)), parse_inst("jnz acc +3").unwrap().execute(&mut cpu));
+    assert_eq!(Ok(Flow::Next), parse_inst("jz acc +3").unwrap().execute(&mut cpu));
+    assert_eq!(Ok(Flow::Next), parse_inst("out a").unwrap().execute(&mut cpu));
+    assert_eq!(vec![7], cpu.output);
+
+    cpu.set(a, i64::MAX);
+    assert_eq!(Err(Fault::Overflow), parse_inst("add a +1").unwrap().execute(&mut cpu));
+    assert_eq!(i64::MAX, cpu.get(a));
+}
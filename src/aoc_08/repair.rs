@@ -0,0 +1,432 @@
+// Finds single-instruction patches which make a looping program terminate.
+//
+// Every instruction has exactly one successor, so the control-flow graph is a
+// forest of trees rooted at the program's exit once its edges are reversed
+// (instructions caught in a loop hang off cycles and never reach the root).
+// One traversal of that reverse tree tells us, for every instruction, whether
+// it terminates and what it adds to the accumulator on the way out. A patch at
+// address `addr` then works exactly when its new successor is in the tree but
+// not in the subtree under `addr` itself, which would lead straight back to
+// the patched instruction.
+//
+// That only holds while control flow and the accumulator don't depend on
+// register values, so programs using conditional branches, or writing the
+// accumulator other than through `acc`, are rejected.
+
+use std::fmt;
+
+use super::isa::Control;
+use super::{Inst, Op, Operand, Program, Reg};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum Goal {
+    // Execution ends by stepping to exactly `program.len()`.
+    #[default]
+    Terminate,
+    // Execution leaves the program at any address, even out of bounds.
+    Exit,
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct RepairOptions {
+    pub goal: Goal,
+    // Also try jumps to any other offset, not only swapping `nop` and `jmp`
+    // while keeping the operand.
+    pub change_operands: bool,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Patch {
+    pub addr: usize,
+    pub original: Inst,
+    pub replacement: Inst,
+    // The accumulator once the patched program has finished.
+    pub accumulator: i64,
+}
+
+impl Patch {
+    pub fn apply(&self, program: &Program) -> Program {
+        let mut patched = program.clone();
+        patched[self.addr] = self.replacement.clone();
+        patched
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Unsupported {
+    pub addr: usize,
+    pub inst: Inst,
+}
+
+impl fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "can't repair programs using {:?} (at {}): its effect depends on register values",
+               self.inst, self.addr)
+    }
+}
+
+impl std::error::Error for Unsupported {}
+
+// Position of a node in the reverse tree, as DFS entry and exit times, used to
+// answer "is `a` an ancestor of `b`" in constant time.
+#[derive(Copy, Clone, Debug)]
+struct TreePos {
+    enter: usize,
+    exit: usize,
+}
+
+// What the instructions along a path add to the accumulator, with the lowest
+// and highest it gets relative to where it started, so an overflow anywhere on
+// the way can be spotted. An i128 can't overflow summing any program that fits
+// in memory.
+#[derive(Copy, Clone, Debug, Default)]
+struct PathSum {
+    total: i128,
+    min: i128,
+    max: i128,
+}
+
+impl PathSum {
+    // This path, preceded by an instruction adding `effect`.
+    fn after(self, effect: i64) -> PathSum {
+        let effect = effect as i128;
+
+        PathSum {
+            total: effect + self.total,
+            min: (effect + self.min).min(0),
+            max: (effect + self.max).max(0),
+        }
+    }
+
+    // The accumulator at the end of the path, starting from `acc`, or None if
+    // it overflows on the way, which faults the console.
+    fn end(self, acc: i64) -> Option<i64> {
+        let acc = acc as i128;
+
+        if acc + self.min < i64::MIN as i128 || acc + self.max > i64::MAX as i128 {
+            None
+        } else {
+            Some((acc + self.total) as i64)
+        }
+    }
+}
+
+struct Analysis<'a> {
+    program: &'a Program,
+    goal: Goal,
+    // Indexed by address, with one extra node at `program.len()` for the exit.
+    tree: Vec<Option<TreePos>>,
+    to_exit: Vec<PathSum>,
+}
+
+impl<'a> Analysis<'a> {
+    fn new(program: &'a Program, goal: Goal) -> Analysis<'a> {
+        let exit = program.len();
+        let mut analysis = Analysis {
+            program,
+            goal,
+            tree: vec![None; exit + 1],
+            to_exit: vec![PathSum::default(); exit + 1],
+        };
+
+        let mut reverse_edges: Vec<Vec<usize>> = vec![vec![]; exit + 1];
+        for (addr, inst) in program.iter().enumerate() {
+            if let Some(next) = analysis.successor(addr, inst) {
+                reverse_edges[next].push(addr);
+            }
+        }
+
+        // Iterative DFS from the exit, so deep chains don't overflow the stack.
+        let mut clock = 0;
+        let mut stack = vec![(exit, 0)];
+        analysis.tree[exit] = Some(TreePos { enter: clock, exit: 0 });
+
+        while let Some((node, child_idx)) = stack.pop() {
+            match reverse_edges[node].get(child_idx) {
+                Some(&child) => {
+                    stack.push((node, child_idx + 1));
+
+                    clock += 1;
+                    analysis.tree[child] = Some(TreePos { enter: clock, exit: 0 });
+                    analysis.to_exit[child] = analysis.to_exit[node].after(acc_effect(&program[child]));
+
+                    stack.push((child, 0));
+                }
+                None => {
+                    clock += 1;
+                    analysis.tree[node].as_mut().unwrap().exit = clock;
+                }
+            }
+        }
+
+        analysis
+    }
+
+    // The address executed after `inst` at `addr`, with anything counting as
+    // an exit under the goal mapped to `program.len()`. None if execution
+    // would leave the program without meeting the goal.
+    fn successor(&self, addr: usize, inst: &Inst) -> Option<usize> {
+        let offset = match inst.spec().control {
+            Control::Next => 1,
+            Control::Jump(_) => inst.jump_offset().unwrap(),
+            Control::Halt => return Some(self.program.len()),
+            Control::Branch(_) => panic!("Programs with branches are rejected up front."),
+        };
+
+        let target = (addr as i64).checked_add(offset)?;
+        let exit = self.program.len() as i64;
+
+        if (0..=exit).contains(&target) {
+            Some(target as usize)
+        } else if self.goal == Goal::Exit {
+            Some(self.program.len())
+        } else {
+            None
+        }
+    }
+
+    fn is_ancestor(&self, ancestor: usize, node: usize) -> bool {
+        match (self.tree[ancestor], self.tree[node]) {
+            (Some(a), Some(n)) => a.enter <= n.enter && n.exit <= a.exit,
+            _ => false,
+        }
+    }
+
+    // The final accumulator if `replacement` at `addr` leads to the exit
+    // without overflowing, given the accumulator before `addr` first executes.
+    fn patched_outcome(&self, addr: usize, replacement: &Inst, acc_before: i64) -> Option<i64> {
+        let next = self.successor(addr, replacement)?;
+
+        if self.tree[next].is_none() || self.is_ancestor(addr, next) {
+            return None;
+        }
+
+        self.to_exit[next].after(acc_effect(replacement)).end(acc_before)
+    }
+
+    // Every jump offset from `addr` which lands on a terminating instruction
+    // (or exits directly) without passing back through `addr`.
+    fn exiting_offsets(&self, addr: usize) -> Vec<i64> {
+        (0..=self.program.len())
+            .filter(|target| self.tree[*target].is_some() && !self.is_ancestor(addr, *target))
+            .map(|target| target as i64 - addr as i64)
+            .collect()
+    }
+}
+
+fn acc_effect(inst: &Inst) -> i64 {
+    match (inst.op(), inst.args().first()) {
+        (Op::Acc, Some(Operand::Imm(value))) => *value,
+        _ => 0,
+    }
+}
+
+fn check_supported(program: &Program) -> Result<(), Unsupported> {
+    for (addr, inst) in program.iter().enumerate() {
+        let is_branch = matches!(inst.spec().control, Control::Branch(_));
+
+        if is_branch || inst.writes() == Some(Reg::ACC) {
+            return Err(Unsupported { addr, inst: inst.clone() });
+        }
+    }
+
+    Ok(())
+}
+
+// Candidate replacements for one instruction: swapping `nop` and `jmp`, and
+// optionally other jump offsets. Changing anything else cannot alter control
+// flow, so it never helps.
+fn candidates(analysis: &Analysis, addr: usize, options: &RepairOptions) -> Vec<Inst> {
+    let inst = &analysis.program[addr];
+    let swapped = match (inst.op(), inst.args()) {
+        (Op::Nop, [Operand::Imm(value)]) => Inst::jmp(*value),
+        (Op::Jmp, [Operand::Imm(offset)]) => Inst::nop(*offset),
+        _ => return vec![],
+    };
+
+    let mut result = vec![swapped];
+
+    if options.change_operands {
+        let keep = inst.args().first().copied();
+
+        result.extend(analysis.exiting_offsets(addr).into_iter()
+            .map(Inst::jmp)
+            .filter(|jump| jump.args().first().copied() != keep));
+    }
+
+    result
+}
+
+// Finds every single-instruction patch that makes `program`, started from
+// address 0, meet the goal. Only instructions the unpatched program actually
+// executes are considered; patching anything else can't change the outcome.
+pub fn find_repairs(program: &Program, options: &RepairOptions) -> Result<Vec<Patch>, Unsupported> {
+    check_supported(program)?;
+
+    let analysis = Analysis::new(program, options.goal);
+
+    // Walk the unpatched program once, recording the accumulator before each
+    // instruction's first execution. Up to that point a patched program
+    // behaves identically.
+    let mut acc_before: Vec<Option<i64>> = vec![None; program.len()];
+    let mut pc = 0;
+    let mut acc = 0;
+
+    while pc < program.len() && acc_before[pc].is_none() {
+        acc_before[pc] = Some(acc);
+
+        // Nothing after an overflow runs, as the console faults there.
+        acc = match acc.checked_add(acc_effect(&program[pc])) {
+            Some(acc) => acc,
+            None => break,
+        };
+
+        match analysis.successor(pc, &program[pc]) {
+            Some(next) => pc = next,
+            None => break,
+        }
+    }
+
+    let mut patches = vec![];
+
+    for (addr, acc) in acc_before.iter().enumerate() {
+        let acc = match acc {
+            Some(acc) => *acc,
+            None => continue,
+        };
+
+        for replacement in candidates(&analysis, addr, options) {
+            if let Some(accumulator) = analysis.patched_outcome(addr, &replacement, acc) {
+                patches.push(Patch {
+                    addr,
+                    original: program[addr].clone(),
+                    replacement,
+                    accumulator,
+                });
+            }
+        }
+    }
+
+    Ok(patches)
+}
+
+#[cfg(test)]
+fn example_program() -> Program {
+    super::parse_program(std::io::Cursor::new("nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6")).unwrap()
+}
+
+#[test]
+fn test_find_repairs() {
+    assert_eq!(
+        vec![Patch { addr: 7, original: Inst::jmp(-4), replacement: Inst::nop(-4), accumulator: 8 }],
+        find_repairs(&example_program(), &RepairOptions::default()).unwrap()
+    );
+}
+
+#[test]
+fn test_find_repairs_changing_operands() {
+    use super::breakpoint::Revisited;
+    use super::{Console, StopReason, Terminated};
+
+    let program = example_program();
+    let options = RepairOptions { change_operands: true, ..Default::default() };
+    let patches = find_repairs(&program, &options).unwrap();
+
+    assert_eq!(9, patches.len());
+
+    // Each patch must really terminate, with the reported accumulator.
+    for patch in &patches {
+        let mut console = Console::new();
+        console.load_program(patch.apply(&program));
+
+        assert_eq!(StopReason::Terminated(Terminated::Normal), console.run_until(Revisited), "{:?}", patch);
+        assert_eq!(patch.accumulator, console.accumulator(), "{:?}", patch);
+    }
+}
+
+#[test]
+fn test_find_repairs_exit_goal() {
+    let program = vec![Inst::nop(-5), Inst::jmp(-1), Inst::jmp(-2)];
+
+    assert!(find_repairs(&program, &RepairOptions::default()).unwrap().is_empty());
+
+    let options = RepairOptions { goal: Goal::Exit, ..Default::default() };
+    assert_eq!(
+        vec![Patch { addr: 0, original: Inst::nop(-5), replacement: Inst::jmp(-5), accumulator: 0 }],
+        find_repairs(&program, &options).unwrap()
+    );
+}
+
+#[test]
+fn test_find_repairs_scales() {
+    // A long run of accumulators followed by a jump back to the start, so
+    // re-running the program per candidate patch would take quadratic time.
+    let mut program: Program = vec![Inst::acc(1); 30_000];
+    program.push(Inst::jmp(-30_000));
+
+    assert_eq!(
+        vec![Patch { addr: 30_000, original: Inst::jmp(-30_000), replacement: Inst::nop(-30_000), accumulator: 30_000 }],
+        find_repairs(&program, &RepairOptions::default()).unwrap()
+    );
+}
+
+#[test]
+fn test_find_repairs_overflow() {
+    use super::breakpoint::Revisited;
+    use super::{Console, StopReason};
+
+    // Either patch leads to `acc +1` with the accumulator already at its
+    // maximum, even though the path out adds nothing overall.
+    let program = super::parse_program(std::io::Cursor::new(
+        "acc +9223372036854775807\nnop +2\njmp -2\nacc +1\nacc -1")).unwrap();
+
+    assert!(find_repairs(&program, &RepairOptions::default()).unwrap().is_empty());
+
+    let mut console = Console::new();
+    console.load_program(Patch { addr: 1, original: Inst::nop(2), replacement: Inst::jmp(2), accumulator: 0 }.apply(&program));
+    assert_eq!(StopReason::Fault(super::Fault::Overflow), console.run_until(Revisited));
+
+    let program = super::parse_program(std::io::Cursor::new(
+        "acc +9223372036854775807\nnop +2\njmp -2\nacc -1\nacc +1")).unwrap();
+
+    assert_eq!(
+        vec![
+            Patch { addr: 1, original: Inst::nop(2), replacement: Inst::jmp(2), accumulator: i64::MAX },
+            Patch { addr: 2, original: Inst::jmp(-2), replacement: Inst::nop(-2), accumulator: i64::MAX },
+        ],
+        find_repairs(&program, &RepairOptions::default()).unwrap()
+    );
+
+    // The unpatched program faults on its second instruction, so nothing
+    // after it can be patched.
+    let program = super::parse_program(std::io::Cursor::new(
+        "acc +9223372036854775807\nacc +1\nnop +0\njmp -1")).unwrap();
+
+    assert!(find_repairs(&program, &RepairOptions::default()).unwrap().is_empty());
+}
+
+#[test]
+fn test_find_repairs_halt_and_unsupported() {
+    let program = super::parse_program(std::io::Cursor::new("acc +2\njmp +2\nhalt\njmp -3\nacc +5")).unwrap();
+
+    assert_eq!(
+        vec![
+            Patch { addr: 1, original: Inst::jmp(2), replacement: Inst::nop(2), accumulator: 2 },
+            Patch { addr: 3, original: Inst::jmp(-3), replacement: Inst::nop(-3), accumulator: 7 },
+        ],
+        find_repairs(&program, &RepairOptions::default()).unwrap()
+    );
+
+    let program = super::parse_program(std::io::Cursor::new("set a +1\njnz a -1")).unwrap();
+
+    assert_eq!(Err(Unsupported { addr: 1, inst: program[1].clone() }),
+               find_repairs(&program, &RepairOptions::default()));
+}
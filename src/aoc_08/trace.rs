@@ -0,0 +1,288 @@
+// Opt-in recording of every instruction a console executes, which can be
+// exported, read back and replayed to check a program behaves the same way.
+
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::numbered_lines;
+
+use super::isa::parse_inst;
+use super::{Console, Inst, Program};
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Step {
+    // The number of instructions executed before this one.
+    pub cycle: usize,
+    pub pc: usize,
+    #[serde(with = "inst_text")]
+    pub inst: Inst,
+    pub acc_before: i64,
+    pub acc_after: i64,
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cycle {}, pc {}: {} (acc {} -> {})",
+               self.cycle, self.pc, self.inst, self.acc_before, self.acc_after)
+    }
+}
+
+// Instructions in JSON are strings in the source format, like `"acc +1"`.
+mod inst_text {
+    use serde::de::Error as _;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use crate::error::Error;
+
+    use super::{parse_inst, Inst};
+
+    pub fn serialize<S: Serializer>(inst: &Inst, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(inst)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Inst, D::Error> {
+        let text = String::deserialize(deserializer)?;
+
+        parse_inst(&text).map_err(|e| match e {
+            Error::Parse { reason, .. } => D::Error::custom(format!("invalid instruction `{}`: {}", text, reason)),
+            other => D::Error::custom(other),
+        })
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Format {
+    // A header row, then one row per step.
+    Csv,
+    // One JSON object per step.
+    JsonLines,
+}
+
+const CSV_HEADER: &str = "cycle,pc,inst,acc_before,acc_after";
+
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Trace {
+    pub steps: Vec<Step>,
+}
+
+impl Trace {
+    // Every address executed more than once, with the cycle it was first
+    // executed again, in cycle order.
+    pub fn first_revisits(&self) -> Vec<(usize, usize)> {
+        let mut seen = std::collections::HashSet::new();
+        let mut revisited = std::collections::HashSet::new();
+
+        self.steps.iter()
+            .filter(|step| !seen.insert(step.pc) && revisited.insert(step.pc))
+            .map(|step| (step.pc, step.cycle))
+            .collect()
+    }
+
+    pub fn write<W: Write>(&self, format: Format, mut out: W) -> io::Result<()> {
+        if format == Format::Csv {
+            writeln!(out, "{}", CSV_HEADER)?;
+        }
+
+        for step in &self.steps {
+            match format {
+                Format::Csv => writeln!(out, "{},{},{},{},{}",
+                                        step.cycle, step.pc, step.inst, step.acc_before, step.acc_after)?,
+                Format::JsonLines => {
+                    serde_json::to_writer(&mut out, step)?;
+                    writeln!(out)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Reads a trace written by `write`. CSV has to be in exactly that
+    // layout, while each JSON line can be any object with the same keys.
+    pub fn read<T: BufRead>(format: Format, input: T) -> Result<Trace, Error> {
+        let mut steps = vec![];
+
+        for line in numbered_lines(input) {
+            let (line_no, text) = line?;
+
+            if format == Format::Csv && line_no == 1 {
+                if text.trim_end() != CSV_HEADER {
+                    return Err(Error::parse(1, &text, format!("expected header: {}", CSV_HEADER)));
+                }
+                continue;
+            }
+
+            let step = match format {
+                Format::Csv => parse_csv_step(&text),
+                Format::JsonLines => serde_json::from_str(&text).map_err(|e| Error::json(&e, &text)),
+            };
+
+            steps.push(step.map_err(|e| e.on_line(line_no))?);
+        }
+
+        Ok(Trace { steps })
+    }
+}
+
+// The number in the `idx`th capture.
+fn number<N: FromStr>(captures: &Captures, idx: usize, line: &str) -> Result<N, Error> where N::Err: fmt::Display {
+    let m = captures.get(idx).unwrap();
+
+    m.as_str().parse::<N>().map_err(|e| Error::parse(m.start() + 1, line, format!("invalid number: {}", e)))
+}
+
+fn parse_csv_step(line: &str) -> Result<Step, Error> {
+    let captures = regex_captures!(r"^(\d+),(\d+),([^,]*),(-?\d+),(-?\d+)\s*$", line)
+        .ok_or_else(|| Error::parse(1, line, "malformed trace step"))?;
+
+    let inst = captures.get(3).unwrap();
+    let inst_column = inst.start() + 1;
+    let inst = parse_inst(inst.as_str()).map_err(|e| match e {
+        Error::Parse { column, reason, .. } => Error::parse(inst_column + column - 1, line, reason),
+        other => other,
+    })?;
+
+    Ok(Step {
+        cycle: number(&captures, 1, line)?,
+        pc: number(&captures, 2, line)?,
+        inst,
+        acc_before: number(&captures, 4, line)?,
+        acc_after: number(&captures, 5, line)?,
+    })
+}
+
+// Where a replay first differed from its trace. `actual` is None if the
+// program stopped before the trace did.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Divergence {
+    pub expected: Step,
+    pub actual: Option<Step>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.actual {
+            Some(actual) => write!(f, "expected {}, got {}", self.expected, actual),
+            None => write!(f, "expected {}, but the program stopped", self.expected),
+        }
+    }
+}
+
+impl std::error::Error for Divergence {}
+
+// Runs `program` for as many steps as `trace` holds, returning the first
+// step that doesn't match, if any.
+pub fn replay(program: &Program, trace: &Trace) -> Option<Divergence> {
+    let mut console = Console::new();
+    console.load_program(program.clone());
+    console.enable_trace();
+
+    for (idx, expected) in trace.steps.iter().enumerate() {
+        console.step();
+
+        let actual = console.trace().unwrap().steps.get(idx);
+
+        if actual != Some(expected) {
+            return Some(Divergence { expected: expected.clone(), actual: actual.cloned() });
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+fn example_trace() -> (Program, Trace) {
+    let program = super::parse_program(io::Cursor::new("nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6")).unwrap();
+
+    let mut console = Console::new();
+    console.load_program(program.clone());
+    console.enable_trace();
+    console.run_until(super::breakpoint::Revisited);
+
+    (program, console.trace().unwrap().clone())
+}
+
+#[test]
+fn test_trace() {
+    let (_, trace) = example_trace();
+
+    assert_eq!(7, trace.steps.len());
+    assert_eq!(
+        Step { cycle: 3, pc: 6, inst: Inst::acc(1), acc_before: 1, acc_after: 2 },
+        trace.steps[3]
+    );
+    assert!(trace.first_revisits().is_empty());
+
+    // Keep going past the loop, so addresses execute again.
+    let (program, _) = example_trace();
+    let mut console = Console::new();
+    console.load_program(program);
+    console.enable_trace();
+    for _ in 0..10 {
+        console.step();
+    }
+
+    assert_eq!(vec![(1, 7), (2, 8), (6, 9)], console.trace().unwrap().first_revisits());
+}
+
+#[test]
+fn test_export_and_replay() {
+    let (program, trace) = example_trace();
+
+    for format in &[Format::Csv, Format::JsonLines] {
+        let mut buf = vec![];
+        trace.write(*format, &mut buf).unwrap();
+
+        let read = Trace::read(*format, io::Cursor::new(buf)).unwrap();
+        assert_eq!(trace, read);
+        assert_eq!(None, replay(&program, &read));
+    }
+
+    let mut buf = vec![];
+    trace.write(Format::JsonLines, &mut buf).unwrap();
+    assert!(String::from_utf8(buf).unwrap()
+        .starts_with("{\"cycle\":0,\"pc\":0,\"inst\":\"nop +0\",\"acc_before\":0,\"acc_after\":0}\n"));
+
+    // Any spacing and key order is fine in JSON.
+    let read = Trace::read(Format::JsonLines, io::Cursor::new(
+        "{ \"inst\": \"acc +1\", \"pc\": 1, \"cycle\": 1, \"acc_after\": 1, \"acc_before\": 0 }\n"
+    )).unwrap();
+    assert_eq!(trace.steps[1], read.steps[0]);
+
+    let mut patched = program.clone();
+    patched[6] = Inst::acc(2);
+
+    let divergence = replay(&patched, &trace).unwrap();
+    assert_eq!(6, divergence.expected.pc);
+    assert_eq!(Some(3), divergence.actual.map(|step| step.acc_after));
+}
+
+#[test]
+fn test_read_errors() {
+    let location = |format: Format, input: &str| match Trace::read(format, io::Cursor::new(input)) {
+        Err(Error::Parse { line, column, .. }) => (line, column),
+        other => panic!("Expected parse error, got {:?}", other),
+    };
+
+    assert_eq!((1, 1), location(Format::Csv, "pc,cycle\n"));
+    assert_eq!((2, 5), location(Format::Csv, "cycle,pc,inst,acc_before,acc_after\n0,0,mul +0,0,0"));
+    assert_eq!(1, location(Format::JsonLines, "{\"cycle\":0}").0);
+    assert_eq!(2, location(Format::JsonLines, "{\"cycle\":0,\"pc\":0,\"inst\":\"nop +0\",\"acc_before\":0,\"acc_after\":0}\n\
+                                               {\"cycle\":1,\"pc\":1,\"inst\":\"mul \\\"1\\\"\",\"acc_before\":0,\"acc_after\":0}").0);
+    assert_eq!((2, 1), location(Format::Csv, "cycle,pc,inst,acc_before,acc_after\n-1,0,nop +0,0,0"));
+    assert_eq!((2, 3), location(Format::Csv, "cycle,pc,inst,acc_before,acc_after\n0,99999999999999999999999,nop +0,0,0"));
+}
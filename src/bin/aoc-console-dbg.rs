@@ -0,0 +1,322 @@
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Write};
+
+use aoc2020::aoc_08::breakpoint::{AccMatches, Breakpoint, CycleLimit, Hit, Revisited};
+use aoc2020::aoc_08::{Console, Reg, StopReason, Terminated, parse_program};
+use aoc2020::open_input;
+
+const USAGE: &str = "Usage: aoc-console-dbg <program file>";
+
+const HELP: &str = "\
+Commands:
+  step [n]       Execute n instructions (default 1).
+  continue       Run until a breakpoint, watch, the first instruction to run
+                 twice or the end of the program, for at most 1000000
+                 instructions.
+  break <addr>   Toggle a breakpoint at an address. `break` lists them.
+  watch acc      Toggle stopping whenever the accumulator changes.
+  print <reg>    Print pc, cycles or a register (acc, a, b, c, d).
+  list           Show the instructions around the program counter.
+  reset          Restart the program from the beginning.
+  quit           Leave the debugger.
+An empty line repeats the previous command.";
+
+// Number of instructions `list` shows either side of the program counter.
+const LIST_CONTEXT: usize = 5;
+
+// The most instructions one `continue` executes.
+const CONTINUE_LIMIT: usize = 1_000_000;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Register {
+    Pc,
+    Cycles,
+    Cpu(Reg),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum Command {
+    Step(usize),
+    Continue,
+    Break(Option<usize>),
+    WatchAcc,
+    Print(Register),
+    List,
+    Reset,
+    Help,
+    Quit,
+}
+
+fn parse_command(input: &str) -> Result<Command, String> {
+    let words: Vec<&str> = input.split_whitespace().collect();
+
+    let number = |word: &str| word.parse::<usize>()
+        .map_err(|_| format!("Expected a number, found `{}`.", word));
+
+    match words.as_slice() {
+        ["step"] | ["s"] => Ok(Command::Step(1)),
+        ["step", n] | ["s", n] => Ok(Command::Step(number(n)?)),
+        ["continue"] | ["c"] => Ok(Command::Continue),
+        ["break"] | ["b"] => Ok(Command::Break(None)),
+        ["break", addr] | ["b", addr] => Ok(Command::Break(Some(number(addr)?))),
+        ["watch", "acc"] | ["w", "acc"] => Ok(Command::WatchAcc),
+        ["print", "pc"] | ["p", "pc"] => Ok(Command::Print(Register::Pc)),
+        ["print", "cycles"] | ["p", "cycles"] => Ok(Command::Print(Register::Cycles)),
+        ["print", name] | ["p", name] => Reg::parse(name)
+            .map(|reg| Command::Print(Register::Cpu(reg)))
+            .ok_or_else(|| format!("Unknown register `{}`.", name)),
+        ["list"] | ["l"] => Ok(Command::List),
+        ["reset"] => Ok(Command::Reset),
+        ["help"] | ["h"] | ["?"] => Ok(Command::Help),
+        ["quit"] | ["q"] => Ok(Command::Quit),
+        _ => Err(format!("Unknown command `{}`. Type `help` for a list of commands.", input.trim())),
+    }
+}
+
+// Checks `inner` only once the console has run past `after` cycles, so a run
+// can leave the instruction it stopped at, and never if `after` is None.
+struct Armed<B> {
+    after: Option<usize>,
+    inner: B,
+}
+
+impl<B: Breakpoint> Breakpoint for Armed<B> {
+    fn check(&mut self, console: &Console) -> Option<Hit> {
+        match self.after {
+            Some(after) if console.cycles > after => self.inner.check(console),
+            _ => None,
+        }
+    }
+}
+
+// Stops at any of the addresses.
+struct AnyAddress<'a>(&'a BTreeSet<usize>);
+
+impl<'a> Breakpoint for AnyAddress<'a> {
+    fn check(&mut self, console: &Console) -> Option<Hit> {
+        if self.0.contains(&console.pc()) {
+            Some(Hit::PcEquals(console.pc()))
+        } else {
+            None
+        }
+    }
+}
+
+struct Debugger {
+    console: Console,
+    breakpoints: BTreeSet<usize>,
+    watch_acc: bool,
+    // Set once `continue` has stopped at the first instruction to run twice,
+    // so later ones carry on around the loop.
+    loop_reported: bool,
+}
+
+impl Debugger {
+    fn new(console: Console) -> Debugger {
+        Debugger { console, breakpoints: BTreeSet::new(), watch_acc: false, loop_reported: false }
+    }
+
+    // Executes up to `limit` instructions (or up to `CONTINUE_LIMIT`, if
+    // None), stopping early at the end of the program, a breakpoint or a
+    // watched change. Without a limit, it also stops the first time an
+    // instruction is about to run again. Returns a description of why
+    // execution stopped.
+    fn run(&mut self, limit: Option<usize>) -> String {
+        let start = self.console.cycles;
+        let watch_acc = self.watch_acc;
+        let mut previous_acc = self.console.accumulator();
+
+        let watch = AccMatches(|acc| {
+            let changed = watch_acc && acc != previous_acc;
+            if !changed {
+                previous_acc = acc;
+            }
+            changed
+        });
+
+        let revisits = if limit.is_none() && !self.loop_reported { Some(start) } else { None };
+
+        let reason = self.console.run_until(
+            watch
+                .or(CycleLimit(start.saturating_add(limit.unwrap_or(CONTINUE_LIMIT))))
+                .or(Armed { after: Some(start), inner: AnyAddress(&self.breakpoints) })
+                .or(Armed { after: revisits, inner: Revisited })
+        );
+
+        let executed = self.console.cycles - start;
+
+        match reason {
+            StopReason::Terminated(Terminated::Normal) => "Program terminated.".to_string(),
+            StopReason::Terminated(Terminated::OutOfBounds { pc }) => format!("Program jumped out of bounds to {}.", pc),
+            StopReason::Fault(fault) => format!("Program faulted: {}.", fault),
+            StopReason::Breakpoint(Hit::Accumulator(acc)) => format!("Watch: acc changed from {} to {}.", previous_acc, acc),
+            StopReason::Breakpoint(Hit::CycleLimit(_)) if limit.is_some() => format!("Stepped {} instruction(s).", executed),
+            StopReason::Breakpoint(Hit::CycleLimit(_)) =>
+                format!("Stopped after {} instructions without terminating.", executed),
+            StopReason::Breakpoint(Hit::PcEquals(pc)) => format!("Hit breakpoint at {}.", pc),
+            StopReason::Breakpoint(Hit::Revisited(pc)) => {
+                self.loop_reported = true;
+                format!("Instruction {} is about to run a second time, so the program loops forever.", pc)
+            }
+            StopReason::Breakpoint(hit) => format!("Stopped: {:?}.", hit),
+        }
+    }
+
+    fn location(&self) -> String {
+        let inst = match self.console.inst() {
+            Some(inst) => inst.to_string(),
+            None => "<end of program>".to_string(),
+        };
+
+        format!("pc={} acc={} cycles={}  {:04}: {}",
+                self.console.pc(), self.console.accumulator(), self.console.cycles,
+                self.console.pc(), inst)
+    }
+
+    fn list(&self) -> String {
+        let pc = self.console.pc();
+        let program = self.console.program();
+        let first = pc.saturating_sub(LIST_CONTEXT);
+        let last = (pc + LIST_CONTEXT).min(program.len().saturating_sub(1));
+
+        (first..=last)
+            .filter_map(|addr| program.get(addr).map(|inst| (addr, inst)))
+            .map(|(addr, inst)| {
+                let marker = if addr == pc { '>' } else { ' ' };
+                let breakpoint = if self.breakpoints.contains(&addr) { '*' } else { ' ' };
+
+                format!("{}{} {:04}: {:<12} (executed {}x)",
+                        marker, breakpoint, addr, inst.to_string(),
+                        self.console.meta_at(addr).unwrap().execution_count)
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    // Executes a command, returning the text to show the user.
+    fn execute(&mut self, command: &Command) -> String {
+        match command {
+            Command::Step(n) => format!("{}\n{}", self.run(Some(*n)), self.location()),
+            Command::Continue => format!("{}\n{}", self.run(None), self.location()),
+            Command::Break(None) if self.breakpoints.is_empty() => "No breakpoints set.".to_string(),
+            Command::Break(None) => format!("Breakpoints: {:?}", self.breakpoints),
+            Command::Break(Some(addr)) => {
+                if self.breakpoints.remove(addr) {
+                    format!("Removed breakpoint at {}.", addr)
+                } else if *addr >= self.console.program().len() {
+                    format!("Address {} is outside the program.", addr)
+                } else {
+                    self.breakpoints.insert(*addr);
+                    format!("Set breakpoint at {}.", addr)
+                }
+            }
+            Command::WatchAcc => {
+                self.watch_acc = !self.watch_acc;
+                format!("Watching acc: {}.", if self.watch_acc { "on" } else { "off" })
+            }
+            Command::Print(Register::Pc) => format!("pc = {}", self.console.pc()),
+            Command::Print(Register::Cycles) => format!("cycles = {}", self.console.cycles),
+            Command::Print(Register::Cpu(reg)) => format!("{} = {}", reg.name(), self.console.register(*reg)),
+            Command::List => self.list(),
+            Command::Reset => {
+                self.console.reset();
+                self.loop_reported = false;
+                format!("Program reset.\n{}", self.location())
+            }
+            Command::Help => HELP.to_string(),
+            Command::Quit => String::new(),
+        }
+    }
+}
+
+fn main() {
+    let filename = std::env::args().nth(1).unwrap_or_else(|| {
+        eprintln!("{}", USAGE);
+        std::process::exit(1);
+    });
+
+    let program = open_input(&filename)
+        .and_then(parse_program)
+        .unwrap_or_else(|e| {
+            eprintln!("{}: {}", filename, e);
+            std::process::exit(1);
+        });
+
+    let mut console = Console::new();
+    console.load_program(program);
+
+    let mut debugger = Debugger::new(console);
+    let mut last_command: Option<Command> = None;
+
+    println!("Loaded {} instructions. Type `help` for a list of commands.", debugger.console.program().len());
+    println!("{}", debugger.location());
+
+    let stdin = io::stdin();
+
+    loop {
+        print!("(dbg) ");
+        io::stdout().flush().unwrap();
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap() == 0 {
+            return; // EOF
+        }
+
+        let command = if line.trim().is_empty() {
+            match &last_command {
+                Some(command) => Ok(command.clone()),
+                None => continue,
+            }
+        } else {
+            parse_command(&line)
+        };
+
+        match command {
+            Ok(Command::Quit) => return,
+            Ok(command) => {
+                println!("{}", debugger.execute(&command));
+                last_command = Some(command);
+            }
+            Err(message) => println!("{}", message),
+        }
+    }
+}
+
+#[test]
+fn test_parse_command() {
+    assert_eq!(Ok(Command::Step(1)), parse_command("step\n"));
+    assert_eq!(Ok(Command::Step(10)), parse_command("s 10"));
+    assert_eq!(Ok(Command::Break(Some(4))), parse_command("break 4"));
+    assert_eq!(Ok(Command::Print(Register::Cycles)), parse_command("print cycles"));
+    assert_eq!(Ok(Command::Print(Register::Cpu(Reg::ACC))), parse_command("p acc"));
+    assert!(parse_command("step ten").is_err());
+    assert!(parse_command("print sp").is_err());
+}
+
+#[test]
+fn test_debugger_run() {
+    let program = parse_program(io::Cursor::new("nop +0\nacc +1\njmp +4\nacc +3\njmp -3\nacc -99\nacc +1\njmp -4\nacc +6")).unwrap();
+    let mut console = Console::new();
+    console.load_program(program);
+    let mut debugger = Debugger::new(console);
+    assert_eq!("pc=0 acc=0 cycles=0  0000: nop +0", debugger.location());
+
+    debugger.execute(&Command::Break(Some(4)));
+    assert_eq!("Hit breakpoint at 4.", debugger.run(None));
+    assert_eq!(5, debugger.console.accumulator());
+    assert_eq!("Stepped 2 instruction(s).", debugger.run(Some(2)));
+
+    debugger.execute(&Command::Reset);
+    assert_eq!("Hit breakpoint at 4.", debugger.run(None));
+    assert_eq!("Instruction 1 is about to run a second time, so the program loops forever.", debugger.run(None));
+    assert_eq!(7, debugger.console.cycles);
+
+    debugger.execute(&Command::WatchAcc);
+    assert_eq!("Watch: acc changed from 5 to 6.", debugger.run(None));
+    assert_eq!(8, debugger.console.cycles);
+
+    // Once the loop is reported, continuing goes round it until the limit.
+    debugger.execute(&Command::WatchAcc);
+    debugger.execute(&Command::Break(Some(4)));
+    assert_eq!(format!("Stopped after {} instructions without terminating.", CONTINUE_LIMIT), debugger.run(None));
+}
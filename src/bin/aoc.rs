@@ -0,0 +1,199 @@
+use aoc2020::aoc_04::export::{Format, PassportWriter};
+use aoc2020::aoc_04::policy::Policy;
+use std::io::{BufRead, Write};
+
+use aoc2020::aoc_04::{PassportReader, RejectionWriter};
+use aoc2020::aoc_07::{BagGraph, Highlight};
+use aoc2020::aoc_08::cfg::{analyse, Cfg};
+use aoc2020::aoc_08::{parse_program, Program};
+use aoc2020::error::Error;
+use aoc2020::open_input;
+use aoc2020::solution::{find_solution, Part};
+
+const USAGE: &str = "\
+Usage: aoc run <day> <a|b> <input file>
+       aoc bags dot <rules file> [--from <bag> | --to <bag>]
+       aoc bags json <rules file>
+       aoc bags tree <rules file> <bag>
+       aoc bags diff <rules file> <rules file>
+       aoc console check <program file>
+       aoc console dot <program file>
+       aoc passports rejected <batch file> [--policy <policy file>]
+       aoc passports export <jsonl|csv|batch> <batch file> [--policy <policy file>]
+       aoc passports stats <batch file> [--policy <policy file>]";
+
+fn exit_with_error(message: impl std::fmt::Display) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
+}
+
+fn load_bags(filename: &str) -> BagGraph {
+    open_input(filename)
+        .and_then(BagGraph::from)
+        .unwrap_or_else(|e| exit_with_error(format!("{}: {}", filename, e)))
+}
+
+fn load_program(filename: &str) -> Program {
+    open_input(filename)
+        .and_then(parse_program)
+        .unwrap_or_else(|e| exit_with_error(format!("{}: {}", filename, e)))
+}
+
+// The policy named by an optional `--policy <file>`, or the default one.
+fn load_policy(args: &[&str]) -> Policy {
+    match args {
+        [] => Policy::default(),
+        ["--policy", policy_file] => std::fs::read_to_string(policy_file)
+            .map_err(Error::from)
+            .and_then(|json| Policy::from_json(&json))
+            .unwrap_or_else(|e| exit_with_error(format!("{}: {}", policy_file, e))),
+        _ => exit_with_error(USAGE),
+    }
+}
+
+// The passport commands read every record, however broken, and only fail if
+// the batch can't be read or the output written.
+
+fn passports_rejected(input: impl BufRead, policy: &Policy, out: impl Write) -> Result<(), Error> {
+    let mut writer = RejectionWriter::new(out, policy)?;
+
+    for passport in PassportReader::with_policy(input, policy) {
+        writer.write(&passport?)?;
+    }
+
+    Ok(())
+}
+
+fn passports_export(input: impl BufRead, format: Format, policy: &Policy, out: impl Write) -> Result<(), Error> {
+    let mut writer = PassportWriter::new(out, format, policy);
+
+    for passport in PassportReader::with_policy(input, policy) {
+        writer.write(&passport?)?;
+    }
+
+    Ok(())
+}
+
+fn passports_stats(input: impl BufRead, policy: &Policy, mut out: impl Write) -> Result<(), Error> {
+    let mut reader = PassportReader::with_policy(input, policy);
+
+    for passport in &mut reader {
+        passport?;
+    }
+
+    write!(out, "{}", reader.stats())?;
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    match args.iter().map(String::as_str).collect::<Vec<&str>>().as_slice() {
+        ["run", day, part, filename] => {
+            let day = day.parse::<u8>()
+                .unwrap_or_else(|_| exit_with_error(format!("Day must be a number: {}", day)));
+            let part = part.parse::<Part>().unwrap_or_else(|e| exit_with_error(e));
+
+            let solution = find_solution(day)
+                .unwrap_or_else(|| exit_with_error(format!("No solution registered for day {}.", day)));
+
+            let mut input = open_input(filename)
+                .unwrap_or_else(|e| exit_with_error(format!("{}: {}", filename, e)));
+
+            match solution.solve(part, &mut input) {
+                Ok(answer) => println!("Day {:02} part {:?}: {}", day, part, answer),
+                Err(e) => exit_with_error(format!("{}: {}", filename, e)),
+            }
+        }
+        ["bags", "dot", filename, highlight @ ..] => {
+            let highlight = match highlight {
+                [] => None,
+                ["--from", bag] => Some(Highlight::From(bag)),
+                ["--to", bag] => Some(Highlight::To(bag)),
+                _ => exit_with_error(USAGE),
+            };
+
+            match load_bags(filename).to_dot(highlight) {
+                Ok(dot) => print!("{}", dot),
+                Err(e) => exit_with_error(e),
+            }
+        }
+        ["bags", "json", filename] => println!("{}", load_bags(filename).to_json()),
+        ["bags", "tree", filename, bag] => match load_bags(filename).packing_tree(bag) {
+            Ok(tree) => {
+                print!("{}", tree);
+                println!("max depth: {}", tree.depth());
+            }
+            Err(e) => exit_with_error(e),
+        },
+        ["bags", "diff", before, after] => match load_bags(before).diff(&load_bags(after)) {
+            diff if diff.is_empty() => println!("No changes."),
+            diff => print!("{}", diff),
+        },
+        ["console", "check", filename] => print!("{}", analyse(&load_program(filename))),
+        ["console", "dot", filename] => {
+            let program = load_program(filename);
+            print!("{}", Cfg::build(&program).to_dot(&program));
+        }
+        ["passports", "rejected", filename, policy @ ..] => {
+            let policy = load_policy(policy);
+
+            open_input(filename)
+                .and_then(|input| passports_rejected(input, &policy, std::io::stdout().lock()))
+                .unwrap_or_else(|e| exit_with_error(format!("{}: {}", filename, e)));
+        }
+        ["passports", "export", format, filename, policy @ ..] => {
+            let format = match *format {
+                "jsonl" => Format::JsonLines,
+                "csv" => Format::Csv,
+                "batch" => Format::Batch,
+                _ => exit_with_error(USAGE),
+            };
+            let policy = load_policy(policy);
+
+            open_input(filename)
+                .and_then(|input| passports_export(input, format, &policy, std::io::stdout().lock()))
+                .unwrap_or_else(|e| exit_with_error(format!("{}: {}", filename, e)));
+        }
+        ["passports", "stats", filename, policy @ ..] => {
+            let policy = load_policy(policy);
+
+            open_input(filename)
+                .and_then(|input| passports_stats(input, &policy, std::io::stdout().lock()))
+                .unwrap_or_else(|e| exit_with_error(format!("{}: {}", filename, e)));
+        }
+        _ => exit_with_error(USAGE),
+    }
+}
+
+#[test]
+fn test_passport_commands() {
+    use std::io::Cursor;
+    let policy = Policy::default();
+    let text = |out: Vec<u8>| String::from_utf8(out).unwrap();
+
+    // A malformed token only makes its own passport invalid.
+    let mut out = vec![];
+    passports_stats(Cursor::new("byr:1980 garbage\n\nbyr:1990"), &policy, &mut out).unwrap();
+    assert!(text(out).starts_with("2 passports, 0 valid, 2 invalid\n"));
+
+    // Every malformed token counts under the same reason.
+    let mut out = vec![];
+    passports_stats(Cursor::new("byr:1980 garbage\n\nbyr:1990 more junk:"), &policy, &mut out).unwrap();
+    assert!(text(out).ends_with("\n  other keys malformed: 2\n"));
+
+    // Runs of blank lines don't make empty passports.
+    let mut out = vec![];
+    passports_stats(Cursor::new("\n\nbyr:1980\n\n\n\n  \n\nbyr:1990\n\n"), &policy, &mut out).unwrap();
+    assert!(text(out).starts_with("2 passports, 0 valid, 2 invalid\n"));
+
+    let mut out = vec![];
+    passports_rejected(Cursor::new("byr:1980 garbage\n\nbyr:1990"), &policy, &mut out).unwrap();
+    let table = text(out);
+    assert!(table.contains("\n1         garbage  -      not `key:value`\n"));
+    assert!(table.lines().last().unwrap().starts_with("2 "));
+
+    let mut out = vec![];
+    passports_export(Cursor::new("byr:1980 garbage\n\nbyr:1990"), Format::Csv, &policy, &mut out).unwrap();
+    assert_eq!(3, text(out).lines().count());
+}
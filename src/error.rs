@@ -0,0 +1,94 @@
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    // Input that could not be parsed. `line` and `column` are 1-indexed, and
+    // `text` is the full line the problem was found on.
+    Parse {
+        line: usize,
+        column: usize,
+        text: String,
+        reason: String,
+    },
+    // A document which parsed, but holds a value breaking a rule about it.
+    // `path` locates the value, like `[2].contains[0].count`.
+    Invalid {
+        path: String,
+        reason: String,
+    },
+    // The input parsed, but the puzzle has no answer for it.
+    NoAnswer(String),
+}
+
+impl Error {
+    // Parsers which only see a single line report errors against line 1;
+    // callers reading many lines use `on_line` to fix up the location.
+    pub fn parse(column: usize, text: &str, reason: impl Into<String>) -> Error {
+        Error::Parse {
+            line: 1,
+            column,
+            text: text.to_string(),
+            reason: reason.into(),
+        }
+    }
+
+    pub fn invalid(path: impl Into<String>, reason: impl Into<String>) -> Error {
+        Error::Invalid { path: path.into(), reason: reason.into() }
+    }
+
+    // Locates a serde_json error in the document it came from.
+    pub fn json(e: &serde_json::Error, input: &str) -> Error {
+        let text = input.lines().nth(e.line().saturating_sub(1)).unwrap_or("");
+        let reason = e.to_string();
+        let reason = reason.split(" at line ").next().unwrap();
+
+        Error::parse(e.column().max(1), text, reason).on_line(e.line())
+    }
+
+    pub fn on_line(self, line_no: usize) -> Error {
+        match self {
+            Error::Parse { column, text, reason, .. } =>
+                Error::Parse { line: line_no, column, text, reason },
+            other => other,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::Parse { line, column, text, reason } => {
+                writeln!(f, "line {}, column {}: {}", line, column, reason)?;
+                writeln!(f, "  {}", text.trim_end())?;
+                write!(f, "  {:>width$}", "^", width = *column)
+            }
+            Error::Invalid { path, reason } => write!(f, "{}: {}", path, reason),
+            Error::NoAnswer(reason) => write!(f, "no answer: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+#[test]
+fn test_display_parse_error() {
+    let error = Error::parse(5, "acc ?1", "invalid operand").on_line(3);
+
+    assert_eq!("line 3, column 5: invalid operand\n  acc ?1\n      ^", error.to_string());
+}
@@ -6,11 +6,20 @@ extern crate maplit;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 
-pub fn create_input_reader() -> BufReader<File> {
-    let filename = std::env::args().nth(1).expect("Must pass filename.");
-    let file = File::open(filename).expect("Could not open file.");
+use crate::error::Error;
 
-    BufReader::new(file)
+pub fn open_input(filename: &str) -> Result<BufReader<File>, Error> {
+    let file = File::open(filename)?;
+
+    Ok(BufReader::new(file))
+}
+
+// Yields each line of input along with its 1-indexed line number, for parsers
+// to report errors against.
+pub fn numbered_lines<T: BufRead>(reader: T) -> impl Iterator<Item=Result<(usize, String), Error>> {
+    reader.lines()
+        .enumerate()
+        .map(|(idx, line)| Ok((idx + 1, line?)))
 }
 
 macro_rules! regex_captures {
@@ -27,35 +36,51 @@ macro_rules! regex_captures {
 
 pub struct RecordReader<T: BufRead> {
     reader: T,
+    lines_read: usize,
 }
 
 impl<T: BufRead> RecordReader<T> {
     pub fn new(reader: T) -> RecordReader<T> {
-        RecordReader { reader }
+        RecordReader { reader, lines_read: 0 }
+    }
+
+    // The number of input lines consumed so far, so the next record starts on
+    // line `lines_read() + 1`.
+    pub fn lines_read(&self) -> usize {
+        self.lines_read
     }
 }
 
 impl<T: BufRead> Iterator for RecordReader<T> {
-    type Item = String;
+    type Item = Result<String, Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
         let mut buf = String::new();
 
         loop {
             match self.reader.read_line(&mut buf) {
-                Ok(bytes) =>
+                Ok(bytes) => {
+                    if bytes > 0 {
+                        self.lines_read += 1;
+                    }
+
                     match (bytes, buf.is_empty()) {
                         (0, true) => return None, // EOF, no pending record.
-                        (0, false) => return Some(buf), // EOF, pending record.
-                        (1, _) => return Some(buf), // Blank line = end of record.
+                        (0, false) => return Some(Ok(buf)), // EOF, pending record.
+                        (1, _) => return Some(Ok(buf)), // Blank line = end of record.
                         _ => {}
-                    },
-                Err(e) => panic!(e),
+                    }
+                }
+                Err(e) => return Some(Err(e.into())),
             }
         }
     }
 }
 
+pub mod error;
+pub mod solution;
+pub mod aoc_01;
+pub mod aoc_02;
 pub mod aoc_03;
 pub mod aoc_04;
 pub mod aoc_05;
@@ -0,0 +1,62 @@
+use std::io::BufRead;
+use std::str::FromStr;
+
+use crate::error::Error;
+use crate::{aoc_01, aoc_02, aoc_03, aoc_04, aoc_05, aoc_06, aoc_07, aoc_08};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Part {
+    A,
+    B,
+}
+
+impl FromStr for Part {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Part, String> {
+        match input {
+            "a" | "A" | "1" => Ok(Part::A),
+            "b" | "B" | "2" => Ok(Part::B),
+            _ => Err(format!("Unknown part: {}", input)),
+        }
+    }
+}
+
+// A day's puzzle. Each part reads the whole puzzle input and produces the
+// answer in the form it would be submitted.
+pub trait Solution {
+    fn part_a(&self, input: &mut dyn BufRead) -> Result<String, Error>;
+    fn part_b(&self, input: &mut dyn BufRead) -> Result<String, Error>;
+
+    fn solve(&self, part: Part, input: &mut dyn BufRead) -> Result<String, Error> {
+        match part {
+            Part::A => self.part_a(input),
+            Part::B => self.part_b(input),
+        }
+    }
+}
+
+pub fn find_solution(day: u8) -> Option<Box<dyn Solution>> {
+    match day {
+        1 => Some(Box::new(aoc_01::Day01)),
+        2 => Some(Box::new(aoc_02::Day02)),
+        3 => Some(Box::new(aoc_03::Day03)),
+        4 => Some(Box::new(aoc_04::Day04)),
+        5 => Some(Box::new(aoc_05::Day05)),
+        6 => Some(Box::new(aoc_06::Day06)),
+        7 => Some(Box::new(aoc_07::Day07)),
+        8 => Some(Box::new(aoc_08::Day08)),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_find_solution() {
+    let mut input = std::io::Cursor::new("1721\n979\n366\n299\n675\n1456\n");
+
+    let solution = find_solution(1).unwrap();
+
+    assert_eq!("514579", solution.solve("a".parse().unwrap(), &mut input).unwrap());
+    assert!(find_solution(26).is_none());
+    assert!("c".parse::<Part>().is_err());
+}